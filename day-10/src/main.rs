@@ -1,14 +1,33 @@
 use std::{env, fs::File, path::Path};
 
 mod pipes;
+use pipes::TileClass;
+
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let (flags, args): (Vec<String>, Vec<String>) =
+        env::args().partition(|arg| arg.starts_with("--"));
+    let mut render = false;
+    let mut colour = false;
+    let mut analyse = false;
+    let mut clean = false;
+    for flag in &flags {
+        match flag.as_str() {
+            "--render" => render = true,
+            "--colour" => {
+                render = true;
+                colour = true;
+            }
+            "--analyse" => analyse = true,
+            "--clean" => clean = true,
+            _ => panic!("unknown flag '{}'", flag),
+        }
+    }
 
     match args.len() {
         1 => {
             panic!("not enough args!");
         }
-        2 => {}
+        2 | 3 => {}
         _ => {
             panic!("too many args!");
         }
@@ -16,10 +35,60 @@ fn main() {
 
     let filepath = &args[1];
     let path = Path::new(filepath);
-    let file = File::open(&path).expect("error opening file!");
+    let file = File::open(path).expect("error opening file!");
+
+    // with no method given, every method is run and checked against the others
+    let method = args.get(2).map(|name| match pipes::AreaMethod::from(name) {
+        Some(method) => method,
+        None => panic!(
+            "unknown method '{}', expected scan, shoelace or flood",
+            name
+        ),
+    });
 
-    let (pt1, pt2) = pipes::pipe(file);
+    let map = match pipes::PipeMap::read(file) {
+        Ok(map) => map,
+        Err(why) => panic!("error: {}", why),
+    };
+    if clean {
+        // the cleaned map is meant for piping into other tools, so print
+        // nothing else
+        for line in map.cleaned() {
+            println!("{}", line);
+        }
+        return;
+    }
+    if render {
+        print!("{}", map.render(colour));
+    }
+    if analyse {
+        print_analysis(&map);
+    }
+
+    let (pt1, pt2) = match pipes::pipe(&map, method) {
+        Ok(totals) => totals,
+        Err(why) => panic!("error: {}", why),
+    };
 
     println!("pt1: {}", pt1);
     println!("pt2: {}", pt2);
 }
+
+fn print_analysis(map: &pipes::PipeMap) {
+    let reports = map.analyse();
+    for class in [
+        TileClass::MainLoop,
+        TileClass::Connected,
+        TileClass::Dangling,
+        TileClass::Ground,
+    ] {
+        let count = reports.iter().filter(|r| r.class == class).count();
+        println!("{:?}: {}", class, count);
+    }
+    for report in reports.iter().filter(|r| !r.broken.is_empty()) {
+        println!(
+            "({}, {}) {:?}: broken {:?}",
+            report.position.0, report.position.1, report.class, report.broken
+        );
+    }
+}
@@ -1,15 +1,15 @@
-use std::collections::HashMap;
+use std::fmt;
 use std::{fs::File, io::prelude::*, io::BufReader};
 
-#[derive(PartialEq)]
-enum Direction {
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Direction {
     North,
     South,
     East,
     West,
 }
 
-#[derive(PartialEq, Hash, Eq, Copy, Clone)]
+#[derive(PartialEq, Hash, Eq, Copy, Clone, Debug)]
 enum PipeType {
     I,
     Bar,
@@ -23,10 +23,111 @@ enum PipeType {
 
 struct Pipe {
     pipe_type: PipeType,
-    directions: (Direction, Direction),
+    directions: Option<(Direction, Direction)>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PipeError {
+    UnknownTile(char),
+    MissingStart,
+    StartUnconnected,
+    StartDangling(usize, usize),
+    StartAmbiguous(usize),
+    BrokenLoop(usize, usize),
+    AreaMismatch(Vec<(AreaMethod, usize)>),
+}
+
+impl fmt::Display for PipeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PipeError::UnknownTile(c) => write!(f, "unknown tile '{}'", c),
+            PipeError::MissingStart => write!(f, "no 'S' tile in input"),
+            PipeError::StartUnconnected => write!(f, "no pipes connect to 'S'"),
+            PipeError::StartDangling(i, j) => {
+                write!(f, "'S' only connects to the pipe at ({}, {})", i, j)
+            }
+            PipeError::StartAmbiguous(n) => {
+                write!(f, "{} pipes connect to 'S', expected exactly 2", n)
+            }
+            PipeError::BrokenLoop(i, j) => write!(f, "loop is broken at ({}, {})", i, j),
+            PipeError::AreaMismatch(counts) => {
+                write!(f, "enclosed tile counts disagree:")?;
+                for (method, count) in counts {
+                    write!(f, " {}={}", method.name(), count)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// What part a tile plays in the map.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum TileClass {
+    /// Part of the loop through `S`.
+    MainLoop,
+    /// A pipe with at least one end that leads nowhere.
+    Dangling,
+    /// A pipe joined up at both ends, but not part of the main loop.
+    Connected,
+    Ground,
+}
+
+/// The classification of a single tile, along with the directions in which
+/// it points at a neighbour that doesn't point back.
+#[derive(Debug, PartialEq)]
+pub struct TileReport {
+    pub position: (usize, usize),
+    pub class: TileClass,
+    pub broken: Vec<Direction>,
+}
+
+/// Ways of counting the tiles enclosed by the loop, which should all agree.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum AreaMethod {
+    /// Row scan counting crossings of north-facing pipes.
+    Scan,
+    /// Shoelace area of the loop vertices, converted to a tile count with
+    /// Pick's theorem.
+    Shoelace,
+    /// Flood fill from the border of a grid at double resolution, so the
+    /// outside can squeeze between adjacent pipes.
+    FloodFill,
+}
+
+impl AreaMethod {
+    pub const ALL: [AreaMethod; 3] = [
+        AreaMethod::Scan,
+        AreaMethod::Shoelace,
+        AreaMethod::FloodFill,
+    ];
+
+    pub fn from(name: &str) -> Option<Self> {
+        match name {
+            "scan" => Some(AreaMethod::Scan),
+            "shoelace" => Some(AreaMethod::Shoelace),
+            "flood" => Some(AreaMethod::FloodFill),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AreaMethod::Scan => "scan",
+            AreaMethod::Shoelace => "shoelace",
+            AreaMethod::FloodFill => "flood",
+        }
+    }
 }
 
 impl Direction {
+    const ALL: [Direction; 4] = [
+        Direction::North,
+        Direction::South,
+        Direction::East,
+        Direction::West,
+    ];
+
     pub fn opposite(&self) -> Direction {
         match *self {
             Direction::North => Direction::South,
@@ -44,164 +145,581 @@ impl Direction {
             Direction::East => (0, 1),
         }
     }
+
+    /// The neighbouring cell in this direction, if it lies on the grid.
+    fn step(&self, i: usize, j: usize, chars: &[Vec<char>]) -> Option<(usize, usize)> {
+        let offset = self.offset();
+        let i = i.checked_add_signed(offset.0 as isize)?;
+        let j = j.checked_add_signed(offset.1 as isize)?;
+        if i < chars.len() && j < chars[i].len() {
+            Some((i, j))
+        } else {
+            None
+        }
+    }
 }
 
-impl Pipe {
-    pub fn from(c: char) -> Self {
-        match c {
-            '|' => Self {
-                pipe_type: PipeType::I,
-                directions: (Direction::North, Direction::South),
-            },
-            '-' => Self {
-                pipe_type: PipeType::Bar,
-                directions: (Direction::East, Direction::West),
-            },
-            'L' => Self {
-                pipe_type: PipeType::L,
-                directions: (Direction::North, Direction::East),
-            },
-            'J' => Self {
-                pipe_type: PipeType::J,
-                directions: (Direction::North, Direction::West),
-            },
-            '7' => Self {
-                pipe_type: PipeType::Seven,
-                directions: (Direction::West, Direction::South),
-            },
-            'F' => Self {
-                pipe_type: PipeType::F,
-                directions: (Direction::East, Direction::South),
-            },
-            'S' => Self {
-                pipe_type: PipeType::S,
-                directions: (Direction::North, Direction::West),
-            },
-            '.' => Self {
-                pipe_type: PipeType::Ground,
-                directions: (Direction::North, Direction::North),
-            },
-            _ => {
-                panic!("Error reading character!!")
+impl PipeType {
+    /// The pipe joining the two given directions, in either order.
+    fn from_directions(a: Direction, b: Direction) -> PipeType {
+        match (a, b) {
+            (Direction::North, Direction::South) | (Direction::South, Direction::North) => {
+                PipeType::I
+            }
+            (Direction::East, Direction::West) | (Direction::West, Direction::East) => {
+                PipeType::Bar
+            }
+            (Direction::North, Direction::East) | (Direction::East, Direction::North) => {
+                PipeType::L
             }
+            (Direction::North, Direction::West) | (Direction::West, Direction::North) => {
+                PipeType::J
+            }
+            (Direction::West, Direction::South) | (Direction::South, Direction::West) => {
+                PipeType::Seven
+            }
+            (Direction::East, Direction::South) | (Direction::South, Direction::East) => {
+                PipeType::F
+            }
+            _ => PipeType::Ground,
         }
     }
 }
 
-pub fn pipe(file: File) -> (i32, i32) {
-    let reader = BufReader::new(file);
-    let mut chars: Vec<Vec<char>> = vec![];
-    let mut s_position = (0, 0);
-    let mut row_count = 0;
-    for l in reader
-        .lines()
-        .map(|x| x.expect("problem opening reading file!"))
-    {
-        let row: Vec<char> = l.chars().collect();
-        if let Some(i) = row.iter().position(|&r| r == 'S') {
-            s_position = (row_count, i);
+impl PipeType {
+    /// Box-drawing character for the pipe, or `None` for ground and `S`.
+    fn glyph(&self) -> Option<char> {
+        match self {
+            PipeType::I => Some('│'),
+            PipeType::Bar => Some('─'),
+            PipeType::L => Some('└'),
+            PipeType::J => Some('┘'),
+            PipeType::Seven => Some('┐'),
+            PipeType::F => Some('┌'),
+            PipeType::S | PipeType::Ground => None,
         }
-        chars.push(row);
-        row_count += 1;
     }
-    let mut points = HashMap::new();
-    let pt1_total = next_pipe(
-        Direction::South,
-        s_position.0 - 1 as usize,
-        s_position.1,
-        &chars,
-        &mut points,
-    );
-    let pt2 = count_contents(&points, &chars) - pt1_total - 1;
-    return ((pt1_total + 1) / 2, pt2);
 }
 
-fn get_loop_total(s_position: (usize, usize), chars: &Vec<Vec<char>>, points: &mut HashMap<(usize, usize), PipeType>,) -> i32 {
-    if s_position.0 > 0 {
-        let above = Pipe::from(chars[s_position.0 - 1][s_position.1]);
-        if above.pipe_type != PipeType::Ground {
-            if above.directions.0 == Direction::South || above.directions.1 == Direction::South {
-                return next_pipe(
-                    Direction::South,
-                    s_position.0 - 1 as usize,
-                    s_position.1,
-                    &chars,
-                    points,
-                );;
+impl Pipe {
+    pub fn from(c: char) -> Result<Self, PipeError> {
+        let (pipe_type, directions) = match c {
+            '|' => (PipeType::I, Some((Direction::North, Direction::South))),
+            '-' => (PipeType::Bar, Some((Direction::East, Direction::West))),
+            'L' => (PipeType::L, Some((Direction::North, Direction::East))),
+            'J' => (PipeType::J, Some((Direction::North, Direction::West))),
+            '7' => (PipeType::Seven, Some((Direction::West, Direction::South))),
+            'F' => (PipeType::F, Some((Direction::East, Direction::South))),
+            // the shape of 'S' is unknown until its neighbours are inspected
+            'S' => (PipeType::S, None),
+            '.' => (PipeType::Ground, None),
+            _ => return Err(PipeError::UnknownTile(c)),
+        };
+        Ok(Self {
+            pipe_type,
+            directions,
+        })
+    }
+
+    fn connects(&self, direction: Direction) -> bool {
+        match self.directions {
+            Some((a, b)) => a == direction || b == direction,
+            None => false,
+        }
+    }
+}
+
+/// Fixed-size set of grid cells, one bit per cell in row-major order.
+struct LoopMask {
+    words: Vec<u64>,
+    width: usize,
+}
+
+impl LoopMask {
+    fn new(height: usize, width: usize) -> Self {
+        Self {
+            words: vec![0; (height * width).div_ceil(64)],
+            width,
+        }
+    }
+
+    fn insert(&mut self, i: usize, j: usize) {
+        let bit = i * self.width + j;
+        self.words[bit / 64] |= 1 << (bit % 64);
+    }
+
+    fn contains(&self, i: usize, j: usize) -> bool {
+        let bit = i * self.width + j;
+        self.words[bit / 64] & (1 << (bit % 64)) != 0
+    }
+}
+
+/// The main loop through `S`, in the order it was walked.
+struct PipeLoop {
+    tiles: Vec<(usize, usize)>,
+    mask: LoopMask,
+    start_type: PipeType,
+}
+
+impl PipeLoop {
+    fn contains(&self, i: usize, j: usize) -> bool {
+        self.mask.contains(i, j)
+    }
+
+    /// The pipe at a loop tile, with `S` swapped for the shape it stands in for.
+    fn pipe_type(&self, i: usize, j: usize, chars: &[Vec<char>]) -> PipeType {
+        if (i, j) == self.tiles[0] {
+            self.start_type
+        } else {
+            Pipe::from(chars[i][j])
+                .expect("loop tiles were already parsed")
+                .pipe_type
+        }
+    }
+
+    fn furthest_distance(&self) -> usize {
+        self.tiles.len() / 2
+    }
+}
+
+/// A parsed pipe map together with the main loop running through it.
+pub struct PipeMap {
+    chars: Vec<Vec<char>>,
+    pipe_loop: PipeLoop,
+}
+
+impl PipeMap {
+    pub fn read(file: File) -> Result<Self, PipeError> {
+        let reader = BufReader::new(file);
+        Self::from_lines(
+            reader
+                .lines()
+                .map(|x| x.expect("problem opening reading file!")),
+        )
+    }
+
+    pub fn from_lines(lines: impl Iterator<Item = String>) -> Result<Self, PipeError> {
+        let mut chars: Vec<Vec<char>> = vec![];
+        let mut s_position = None;
+        for (row_count, l) in lines.enumerate() {
+            let row: Vec<char> = l.chars().collect();
+            if let Some(i) = row.iter().position(|&r| r == 'S') {
+                s_position = Some((row_count, i));
+            }
+            chars.push(row);
+        }
+        let s_position = s_position.ok_or(PipeError::MissingStart)?;
+
+        let pipe_loop = trace_loop(s_position, &chars)?;
+        Ok(Self { chars, pipe_loop })
+    }
+
+    pub fn furthest_distance(&self) -> usize {
+        self.pipe_loop.furthest_distance()
+    }
+
+    pub fn enclosed_count(&self, method: AreaMethod) -> usize {
+        match method {
+            AreaMethod::Scan => scan_tiles(&self.pipe_loop, &self.chars).len(),
+            AreaMethod::Shoelace => shoelace_count(&self.pipe_loop),
+            AreaMethod::FloodFill => self.enclosed_tiles().len(),
+        }
+    }
+
+    /// Every tile inside the loop, in row-major order.
+    pub fn enclosed_tiles(&self) -> Vec<(usize, usize)> {
+        flood_fill_tiles(&self.pipe_loop, &self.chars)
+    }
+
+    /// Counts the enclosed tiles with every method, failing if any disagree.
+    pub fn cross_check(&self) -> Result<usize, PipeError> {
+        let counts: Vec<(AreaMethod, usize)> = AreaMethod::ALL
+            .iter()
+            .map(|&method| (method, self.enclosed_count(method)))
+            .collect();
+        if counts.iter().all(|&(_, count)| count == counts[0].1) {
+            Ok(counts[0].1)
+        } else {
+            Err(PipeError::AreaMismatch(counts))
+        }
+    }
+
+    /// The pipe at any tile, with `S` swapped for the shape it stands in for.
+    fn pipe_at(&self, i: usize, j: usize) -> Pipe {
+        if (i, j) == self.pipe_loop.tiles[0] {
+            let directions = start_directions((i, j), &self.chars).ok();
+            Pipe {
+                pipe_type: self.pipe_loop.start_type,
+                directions,
             }
+        } else {
+            Pipe::from(self.chars[i][j]).unwrap_or(Pipe {
+                pipe_type: PipeType::Ground,
+                directions: None,
+            })
         }
     }
-    if s_position.0 < chars.len() - 1 {
-        let below = Pipe::from(chars[s_position.0 + 1][s_position.1]);
-        if below.pipe_type != PipeType::Ground {
-            if below.directions.0 == Direction::North || below.directions.1 == Direction::North {
-                return 0;
+
+    /// The directions a tile's pipe points in without a matching pipe
+    /// pointing back.
+    fn broken_connections(&self, i: usize, j: usize) -> Vec<Direction> {
+        let (a, b) = match self.pipe_at(i, j).directions {
+            Some(directions) => directions,
+            None => return vec![],
+        };
+        [a, b]
+            .into_iter()
+            .filter(|direction| match direction.step(i, j, &self.chars) {
+                Some((n_i, n_j)) => !self.pipe_at(n_i, n_j).connects(direction.opposite()),
+                None => true,
+            })
+            .collect()
+    }
+
+    /// Classifies every tile on the map, in row-major order.
+    pub fn analyse(&self) -> Vec<TileReport> {
+        let mut reports = vec![];
+        for (i, row) in self.chars.iter().enumerate() {
+            for j in 0..row.len() {
+                let broken = self.broken_connections(i, j);
+                let class = if self.pipe_loop.contains(i, j) {
+                    TileClass::MainLoop
+                } else if self.pipe_at(i, j).pipe_type == PipeType::Ground {
+                    TileClass::Ground
+                } else if broken.is_empty() {
+                    TileClass::Connected
+                } else {
+                    TileClass::Dangling
+                };
+                reports.push(TileReport {
+                    position: (i, j),
+                    class,
+                    broken,
+                });
             }
         }
+        reports
     }
-    if s_position.1 > 0 {
-        let left = Pipe::from(chars[s_position.0][s_position.1 - 1]);
-        if left.pipe_type != PipeType::Ground {
-            if left.directions.0 == Direction::East || left.directions.1 == Direction::East {
-                return 0;
+
+    /// The input with every tile off the main loop replaced by `.`, which
+    /// can be fed back in through `from_lines`.
+    pub fn cleaned(&self) -> Vec<String> {
+        self.chars
+            .iter()
+            .enumerate()
+            .map(|(i, row)| {
+                row.iter()
+                    .enumerate()
+                    .map(|(j, &c)| {
+                        if self.pipe_loop.contains(i, j) {
+                            c
+                        } else {
+                            '.'
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Redraws the map with box-drawing characters, marking enclosed tiles.
+    pub fn render(&self, colour: bool) -> String {
+        const DIM: &str = "\x1b[2m";
+        const START: &str = "\x1b[1;33m";
+        const INSIDE: &str = "\x1b[32m";
+        const RESET: &str = "\x1b[0m";
+
+        let width = self.chars.iter().map(|r| r.len()).max().unwrap_or(0);
+        let mut inside = LoopMask::new(self.chars.len(), width);
+        for (i, j) in self.enclosed_tiles() {
+            inside.insert(i, j);
+        }
+
+        let mut out = String::new();
+        for (i, row) in self.chars.iter().enumerate() {
+            for (j, &c) in row.iter().enumerate() {
+                if self.pipe_loop.contains(i, j) {
+                    let glyph = self.pipe_loop.pipe_type(i, j, &self.chars).glyph();
+                    let glyph = glyph.expect("loop tiles are all pipes");
+                    if colour && (i, j) == self.pipe_loop.tiles[0] {
+                        out.push_str(&format!("{}{}{}", START, glyph, RESET));
+                    } else {
+                        out.push(glyph);
+                    }
+                } else if inside.contains(i, j) {
+                    if colour {
+                        out.push_str(&format!("{}●{}", INSIDE, RESET));
+                    } else {
+                        out.push('●');
+                    }
+                } else if colour {
+                    let pipe_type = Pipe::from(c).map_or(PipeType::Ground, |p| p.pipe_type);
+                    let glyph = pipe_type.glyph().unwrap_or('·');
+                    out.push_str(&format!("{}{}{}", DIM, glyph, RESET));
+                } else {
+                    out.push('·');
+                }
             }
+            out.push('\n');
         }
+        out
     }
+}
 
-    return 0;
+pub fn pipe(map: &PipeMap, method: Option<AreaMethod>) -> Result<(i32, i32), PipeError> {
+    let pt1 = map.furthest_distance() as i32;
+    let pt2 = match method {
+        Some(method) => map.enclosed_count(method),
+        None => map.cross_check()?,
+    };
+    Ok((pt1, pt2 as i32))
 }
 
-fn next_pipe(
-    coming_from: Direction,
-    i: usize,
-    j: usize,
-    chars: &Vec<Vec<char>>,
-    points: &mut HashMap<(usize, usize), PipeType>,
-) -> i32 {
-    let pipe = Pipe::from(chars[i][j]);
-    points.insert((i, j), pipe.pipe_type);
-    if pipe.pipe_type == PipeType::S {
-        return 0;
+/// The two directions leading out of `S`, worked out from which of its
+/// neighbours have a pipe pointing back at it.
+fn start_directions(
+    s_position: (usize, usize),
+    chars: &[Vec<char>],
+) -> Result<(Direction, Direction), PipeError> {
+    let mut connections = vec![];
+    for direction in Direction::ALL {
+        if let Some((i, j)) = direction.step(s_position.0, s_position.1, chars) {
+            if Pipe::from(chars[i][j])?.connects(direction.opposite()) {
+                connections.push((direction, (i, j)));
+            }
+        }
     }
-    let going_to;
-    if pipe.directions.0 == coming_from {
-        going_to = pipe.directions.1;
-    } else {
-        going_to = pipe.directions.0;
+
+    match connections[..] {
+        [] => Err(PipeError::StartUnconnected),
+        [(_, (i, j))] => Err(PipeError::StartDangling(i, j)),
+        [(a, _), (b, _)] => Ok((a, b)),
+        _ => Err(PipeError::StartAmbiguous(connections.len())),
     }
-    let offset = going_to.offset();
-    return 1 + next_pipe(
-        going_to.opposite(),
-        ((i as i32) + offset.0) as usize,
-        ((j as i32) + offset.1) as usize,
-        chars,
-        points,
-    );
 }
 
-fn count_contents(points: &HashMap<(usize, usize), PipeType>, chars: &Vec<Vec<char>>) -> i32 {
-    let mut in_bounds = false;
-    let mut pipe_count = 0;
-    for i in 0..chars.len() {
-        for j in 0..chars[i].len() {
-            if in_bounds {
-                pipe_count += 1;
-                if let Some(pipe) = points.get(&(i, j)) {
-                    match *pipe {
-                        PipeType::J | PipeType::Seven | PipeType::I | PipeType::S => {
-                            in_bounds = false;
-                        }
-                        _ => {}
-                    };
-                }
-            } else {
-                if let Some(_) = points.get(&(i, j)) {
-                    in_bounds = true;
-                    pipe_count += 1;
+/// Walks the loop from `S` until it arrives back at `S`.
+fn trace_loop(s_position: (usize, usize), chars: &[Vec<char>]) -> Result<PipeLoop, PipeError> {
+    let (start_direction, end_direction) = start_directions(s_position, chars)?;
+    let width = chars.iter().map(|r| r.len()).max().unwrap_or(0);
+    let mut mask = LoopMask::new(chars.len(), width);
+    let mut tiles = vec![s_position];
+    mask.insert(s_position.0, s_position.1);
+
+    let mut going_to = start_direction;
+    let (mut i, mut j) = s_position;
+    loop {
+        (i, j) = going_to
+            .step(i, j, chars)
+            .ok_or(PipeError::BrokenLoop(i, j))?;
+        let pipe = Pipe::from(chars[i][j])?;
+        if pipe.pipe_type == PipeType::S {
+            break;
+        }
+        let coming_from = going_to.opposite();
+        let (a, b) = match pipe.directions {
+            Some(directions) if pipe.connects(coming_from) => directions,
+            _ => return Err(PipeError::BrokenLoop(i, j)),
+        };
+        if mask.contains(i, j) {
+            return Err(PipeError::BrokenLoop(i, j));
+        }
+        mask.insert(i, j);
+        tiles.push((i, j));
+        going_to = if a == coming_from { b } else { a };
+    }
+
+    Ok(PipeLoop {
+        tiles,
+        mask,
+        start_type: PipeType::from_directions(start_direction, end_direction),
+    })
+}
+
+/// Scans each row left to right, flipping between inside and outside the
+/// loop whenever a loop pipe with a northward connection is crossed.
+fn scan_tiles(pipe_loop: &PipeLoop, chars: &[Vec<char>]) -> Vec<(usize, usize)> {
+    let mut enclosed = vec![];
+    for (i, row) in chars.iter().enumerate() {
+        let mut in_bounds = false;
+        for j in 0..row.len() {
+            if pipe_loop.contains(i, j) {
+                if let PipeType::I | PipeType::L | PipeType::J = pipe_loop.pipe_type(i, j, chars) {
+                    in_bounds = !in_bounds;
                 }
+            } else if in_bounds {
+                enclosed.push((i, j));
             }
         }
     }
 
-    return pipe_count;
+    enclosed
+}
+
+/// Twice the signed area of the loop polygon gives its area `A`, and Pick's
+/// theorem `A = inside + boundary / 2 - 1` then gives the inside tile count.
+fn shoelace_count(pipe_loop: &PipeLoop) -> usize {
+    let tiles = &pipe_loop.tiles;
+    let mut double_area: i64 = 0;
+    for (k, &(i, j)) in tiles.iter().enumerate() {
+        let (next_i, next_j) = tiles[(k + 1) % tiles.len()];
+        double_area += (j as i64) * (next_i as i64) - (next_j as i64) * (i as i64);
+    }
+    let boundary = tiles.len() as i64;
+    (double_area.abs() / 2 - boundary / 2 + 1) as usize
+}
+
+/// Fills the loop's outside on a doubled grid, so gaps between pipes can be squeezed through.
+fn flood_fill_tiles(pipe_loop: &PipeLoop, chars: &[Vec<char>]) -> Vec<(usize, usize)> {
+    let height = chars.len();
+    let width = chars.iter().map(|r| r.len()).max().unwrap_or(0);
+    let (big_height, big_width) = (2 * height + 1, 2 * width + 1);
+
+    let mut walls = LoopMask::new(big_height, big_width);
+    let tiles = &pipe_loop.tiles;
+    for (k, &(i, j)) in tiles.iter().enumerate() {
+        let (next_i, next_j) = tiles[(k + 1) % tiles.len()];
+        walls.insert(2 * i + 1, 2 * j + 1);
+        walls.insert(i + next_i + 1, j + next_j + 1);
+    }
+
+    let mut outside = LoopMask::new(big_height, big_width);
+    let mut stack: Vec<(usize, usize)> = vec![(0, 0)];
+    outside.insert(0, 0);
+    while let Some((i, j)) = stack.pop() {
+        let neighbours = [
+            (i.wrapping_sub(1), j),
+            (i + 1, j),
+            (i, j.wrapping_sub(1)),
+            (i, j + 1),
+        ];
+        for (n_i, n_j) in neighbours {
+            if n_i < big_height
+                && n_j < big_width
+                && !walls.contains(n_i, n_j)
+                && !outside.contains(n_i, n_j)
+            {
+                outside.insert(n_i, n_j);
+                stack.push((n_i, n_j));
+            }
+        }
+    }
+
+    let mut enclosed = vec![];
+    for (i, row) in chars.iter().enumerate() {
+        for j in 0..row.len() {
+            if !pipe_loop.contains(i, j) && !outside.contains(2 * i + 1, 2 * j + 1) {
+                enclosed.push((i, j));
+            }
+        }
+    }
+    enclosed
+}
+
+#[cfg(test)]
+fn start_type(s_position: (usize, usize), chars: &[Vec<char>]) -> Result<PipeType, PipeError> {
+    let (a, b) = start_directions(s_position, chars)?;
+    Ok(PipeType::from_directions(a, b))
+}
+
+#[cfg(test)]
+fn grid(rows: &[&str]) -> Vec<Vec<char>> {
+    rows.iter().map(|r| r.chars().collect()).collect()
+}
+
+#[test]
+fn start_shape() {
+    let chars = grid(&["7-F7-", ".FJ|7", "SJLL7", "|F--J", "LJ.LJ"]);
+    assert_eq!(start_type((2, 0), &chars), Ok(PipeType::F));
+
+    let chars = grid(&[".....", ".S-7.", ".|.|.", ".L-J.", "....."]);
+    assert_eq!(start_type((1, 1), &chars), Ok(PipeType::F));
+}
+
+#[test]
+fn start_connection_errors() {
+    let chars = grid(&["...", ".S.", "..."]);
+    assert_eq!(start_type((1, 1), &chars), Err(PipeError::StartUnconnected));
+
+    let chars = grid(&[".|.", "-S-", ".|."]);
+    assert_eq!(
+        start_type((1, 1), &chars),
+        Err(PipeError::StartAmbiguous(4))
+    );
+}
+
+#[test]
+fn long_loop() {
+    // a 2000x2000 ring is 7996 tiles long, deep enough to trouble recursion
+    let size = 2000;
+    let mut rows = vec![format!("S{}7", "-".repeat(size - 2))];
+    rows.extend((1..size - 1).map(|_| format!("|{}|", ".".repeat(size - 2))));
+    rows.push(format!("L{}J", "-".repeat(size - 2)));
+    let chars = grid(&rows.iter().map(String::as_str).collect::<Vec<_>>());
+
+    let pipe_loop = trace_loop((0, 0), &chars).unwrap();
+    assert_eq!(pipe_loop.tiles.len(), 4 * (size - 1));
+    assert_eq!(pipe_loop.furthest_distance(), 2 * (size - 1));
+    assert!(pipe_loop.contains(size - 1, size - 1));
+    assert!(!pipe_loop.contains(1, 1));
+    let inside = (size - 2) * (size - 2);
+    assert_eq!(scan_tiles(&pipe_loop, &chars).len(), inside);
+    assert_eq!(shoelace_count(&pipe_loop), inside);
+    assert_eq!(flood_fill_tiles(&pipe_loop, &chars).len(), inside);
+}
+
+#[test]
+fn area_methods_agree() {
+    // the squeeze-between-pipes example from the puzzle text
+    let chars = grid(&[
+        "..........",
+        ".S------7.",
+        ".|F----7|.",
+        ".||....||.",
+        ".||....||.",
+        ".|L-7F-J|.",
+        ".|..||..|.",
+        ".L--JL--J.",
+        "..........",
+    ]);
+    let pipe_loop = trace_loop((1, 1), &chars).unwrap();
+    let expected = vec![(6, 2), (6, 3), (6, 6), (6, 7)];
+    assert_eq!(scan_tiles(&pipe_loop, &chars), expected);
+    assert_eq!(flood_fill_tiles(&pipe_loop, &chars), expected);
+    assert_eq!(shoelace_count(&pipe_loop), 4);
+}
+
+#[test]
+fn analyse_tiles() {
+    let lines = ["7-F7-", ".FJ|7", "SJLL7", "|F--J", "LJ.LJ"];
+    let map = PipeMap::from_lines(lines.iter().map(|l| l.to_string())).unwrap();
+    let reports = map.analyse();
+
+    assert_eq!(reports[0].class, TileClass::Dangling);
+    assert_eq!(reports[0].broken, vec![Direction::West, Direction::South]);
+    assert_eq!(reports[5].class, TileClass::Ground);
+    assert_eq!(reports[10].class, TileClass::MainLoop);
+    assert!(reports[10].broken.is_empty());
+    // the 'L' at (2, 2) points into a 'J' and an 'L', neither pointing back
+    assert_eq!(reports[12].class, TileClass::Dangling);
+    assert_eq!(reports[12].broken, vec![Direction::North, Direction::East]);
+
+    let cleaned = map.cleaned();
+    assert_eq!(cleaned, vec!["..F7.", ".FJ|.", "SJ.L7", "|F--J", "LJ..."]);
+    let reread = PipeMap::from_lines(cleaned.into_iter()).unwrap();
+    assert_eq!(reread.furthest_distance(), map.furthest_distance());
+    assert!(reread
+        .analyse()
+        .iter()
+        .all(|r| r.class == TileClass::MainLoop || r.class == TileClass::Ground));
+}
+
+#[test]
+fn render_plain() {
+    let chars = grid(&["-L|F7", "7S-7|", "L|7||", "-L-J|", "L|-JF"]);
+    let map = PipeMap {
+        pipe_loop: trace_loop((1, 1), &chars).unwrap(),
+        chars,
+    };
+    assert_eq!(map.render(false), "·····\n·┌─┐·\n·│●│·\n·└─┘·\n·····\n");
 }
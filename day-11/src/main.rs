@@ -1,26 +1,264 @@
 use std::{env, fs::File, path::Path};
 
+mod query;
 mod space;
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
+use output::{Json, Output, Verbosity};
+use query::{GalaxyQuery, Metric};
+use space::{Density, EmptyLines, Image};
 
-    match args.len() {
-        1 => {
-            panic!("not enough args!");
-        }
-        2 => {}
-        _ => {
-            panic!("too many args!");
+fn main() {
+    let (flags, args): (Vec<String>, Vec<String>) =
+        env::args().partition(|arg| arg.starts_with("--"));
+    let mut density = false;
+    let mut verbosity = Verbosity::Normal;
+    let mut json = false;
+    let mut metric = None;
+    let mut pairs = vec![];
+    let mut nearest = vec![];
+    let mut farthest = false;
+    let mut sum = false;
+    for flag in &flags {
+        let (name, value) = match flag.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (flag.as_str(), None),
+        };
+        match (name, value) {
+            ("--density", None) => density = true,
+            ("--quiet", None) => verbosity = Verbosity::Quiet,
+            ("--verbose", None) => verbosity = Verbosity::Verbose,
+            ("--json", None) => json = true,
+            ("--farthest", None) => farthest = true,
+            ("--sum", None) => sum = true,
+            ("--metric", Some(value)) => match Metric::from(value) {
+                Some(m) => metric = Some(m),
+                None => panic!(
+                    "unknown metric '{}', expected manhattan, chebyshev or euclidean",
+                    value
+                ),
+            },
+            ("--pair", Some(value)) => match value.split_once(',') {
+                Some((a, b)) => pairs.push((parse_number(a), parse_number(b))),
+                None => panic!("--pair expects two galaxy numbers, got '{}'", value),
+            },
+            ("--nearest", Some(value)) => nearest.push(parse_number(value)),
+            _ => panic!("unknown flag '{}'", flag),
         }
-    };
+    }
+
+    if args.len() < 2 {
+        panic!("not enough args!");
+    }
 
     let filepath = &args[1];
     let path = Path::new(filepath);
-    let file = File::open(&path).expect("error opening file!");
+    let file = File::open(path).expect("error opening file!");
+    let image = Image::read(file);
+    let mut out = Output::new(verbosity, json);
+    print_image(&mut out, &image, density);
+
+    // any further args are expansion factors, either one for both axes or a
+    // "horizontal,vertical" pair; without them, answer both parts
+    let factors: Vec<(usize, usize)> = args[2..].iter().map(|arg| parse_factors(arg)).collect();
+
+    if factors.is_empty() {
+        let (_, sums) = space::space(&image, &[(2, 2), (1_000_000, 1_000_000)], density);
+        out.answer("pt1", sums[0]);
+        out.answer("pt2", sums[1]);
+    } else {
+        let (formula, sums) = space::space(&image, &factors, density);
+        if let Some(formula) = formula {
+            let value = Json::object(vec![
+                ("base", formula.base.into()),
+                ("per_column", formula.per_column.into()),
+                ("per_row", formula.per_row.into()),
+            ]);
+            out.field(Verbosity::Normal, "formula", value, || {
+                format!(
+                    "distance sum: {} + {} * (horizontal - 1) + {} * (vertical - 1)",
+                    formula.base, formula.per_column, formula.per_row
+                )
+            });
+        }
+        let value = factors
+            .iter()
+            .zip(&sums)
+            .map(|(&(h, v), &sum)| {
+                Json::object(vec![
+                    ("horizontal", h.into()),
+                    ("vertical", v.into()),
+                    ("sum", sum.into()),
+                ])
+            })
+            .collect();
+        out.field(Verbosity::Quiet, "sums", Json::Array(value), || {
+            factors
+                .iter()
+                .zip(&sums)
+                .map(|((h, v), sum)| format!("factor {},{}: {}", h, v, sum))
+                .collect::<Vec<String>>()
+                .join("\n")
+        });
+    }
+
+    if metric.is_some() || sum || !pairs.is_empty() || !nearest.is_empty() || farthest {
+        // queries run against the first expansion given, or the part one image
+        let (h, v) = factors.first().copied().unwrap_or((2, 2));
+        let query = if density {
+            image.query(&Density(h), &Density(v))
+        } else {
+            image.query(&EmptyLines(h), &EmptyLines(v))
+        };
+        let metric = metric.unwrap_or(Metric::Manhattan);
+        print_queries(&mut out, &query, metric, sum, &pairs, &nearest, farthest);
+    }
+
+    out.finish();
+}
+
+fn print_image(out: &mut Output, image: &Image, density: bool) {
+    let rule = if density { "density" } else { "empty lines" };
+    out.field(Verbosity::Verbose, "density", Json::Bool(density), || {
+        format!("expansion rule: {}", rule)
+    });
 
-    let (pt1, pt2) = space::space(file);
+    let galaxies = image.galaxies();
+    let value = galaxies
+        .iter()
+        .enumerate()
+        .map(|(k, &(x, y))| {
+            Json::object(vec![
+                ("number", (k + 1).into()),
+                ("x", x.into()),
+                ("y", y.into()),
+            ])
+        })
+        .collect();
+    out.field(Verbosity::Verbose, "galaxies", Json::Array(value), || {
+        galaxies
+            .iter()
+            .enumerate()
+            .map(|(k, (x, y))| format!("galaxy {}: ({}, {})", k + 1, x, y))
+            .collect::<Vec<String>>()
+            .join("\n")
+    });
 
-    println!("pt1: {}", pt1);
-    println!("pt2: {}", pt2);
+    let rows = image.empty_rows();
+    let text = format!("empty rows: {:?}", rows);
+    out.field(Verbosity::Verbose, "empty_rows", rows.into(), || text);
+    let cols = image.empty_columns();
+    let text = format!("empty columns: {:?}", cols);
+    out.field(Verbosity::Verbose, "empty_columns", cols.into(), || text);
+}
+
+fn print_queries(
+    out: &mut Output,
+    query: &GalaxyQuery,
+    metric: Metric,
+    sum: bool,
+    pairs: &[(usize, usize)],
+    nearest: &[usize],
+    farthest: bool,
+) {
+    let name = format!("{:?}", metric).to_lowercase();
+    out.field(Verbosity::Normal, "metric", name.as_str().into(), || {
+        format!("metric: {}", name)
+    });
+
+    // Euclidean sums check every pair, so only work one out when asked to
+    if sum {
+        let sum = query.distance_sum(metric);
+        out.field(Verbosity::Quiet, "distance_sum", sum.into(), || {
+            format!("distance sum: {}", sum)
+        });
+    }
+
+    let distances: Vec<Option<f64>> = pairs
+        .iter()
+        .map(|&(a, b)| query.distance(a, b, metric))
+        .collect();
+    if !pairs.is_empty() {
+        let value = pairs
+            .iter()
+            .zip(&distances)
+            .map(|(&(a, b), &d)| {
+                Json::object(vec![
+                    ("a", a.into()),
+                    ("b", b.into()),
+                    ("distance", d.into()),
+                ])
+            })
+            .collect();
+        out.field(Verbosity::Quiet, "pairs", Json::Array(value), || {
+            pairs
+                .iter()
+                .zip(&distances)
+                .map(|((a, b), d)| match d {
+                    Some(d) => format!("galaxies {} and {}: {}", a, b, d),
+                    None => format!("galaxies {} and {}: no such galaxy", a, b),
+                })
+                .collect::<Vec<String>>()
+                .join("\n")
+        });
+    }
+
+    let found: Vec<Option<(usize, f64)>> = nearest
+        .iter()
+        .map(|&number| query.nearest(number, metric))
+        .collect();
+    if !nearest.is_empty() {
+        let value = nearest
+            .iter()
+            .zip(&found)
+            .map(|(&number, found)| {
+                Json::object(vec![
+                    ("galaxy", number.into()),
+                    ("nearest", found.map(|(other, _)| other).into()),
+                    ("distance", found.map(|(_, d)| d).into()),
+                ])
+            })
+            .collect();
+        out.field(Verbosity::Quiet, "nearest", Json::Array(value), || {
+            nearest
+                .iter()
+                .zip(&found)
+                .map(|(number, found)| match found {
+                    Some((other, d)) => format!("nearest to {}: galaxy {} at {}", number, other, d),
+                    None => format!("nearest to {}: no such galaxy", number),
+                })
+                .collect::<Vec<String>>()
+                .join("\n")
+        });
+    }
+
+    if farthest {
+        let found = query.farthest_pair(metric);
+        let value = Json::object(vec![
+            ("a", found.map(|(a, _, _)| a).into()),
+            ("b", found.map(|(_, b, _)| b).into()),
+            ("distance", found.map(|(_, _, d)| d).into()),
+        ]);
+        out.field(Verbosity::Quiet, "farthest", value, || match found {
+            Some((a, b, d)) => format!("farthest pair: galaxies {} and {} at {}", a, b, d),
+            None => "farthest pair: fewer than two galaxies".to_string(),
+        });
+    }
+}
+
+fn parse_number(s: &str) -> usize {
+    match s.parse() {
+        Ok(number) => number,
+        Err(_) => panic!("expected a galaxy number, got '{}'", s),
+    }
+}
+
+fn parse_factors(arg: &str) -> (usize, usize) {
+    let parse = |s: &str| match s.parse() {
+        Ok(factor) if factor > 0 => factor,
+        _ => panic!("expansion factor must be a positive integer, got '{}'", s),
+    };
+    match arg.split_once(',') {
+        Some((h, v)) => (parse(h), parse(v)),
+        None => (parse(arg), parse(arg)),
+    }
 }
@@ -0,0 +1,334 @@
+/// A galaxy position as `(x, y)`, after any expansion.
+pub type Point = (i64, i64);
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Metric {
+    Manhattan,
+    Chebyshev,
+    Euclidean,
+}
+
+impl Metric {
+    pub fn from(name: &str) -> Option<Self> {
+        match name {
+            "manhattan" => Some(Metric::Manhattan),
+            "chebyshev" => Some(Metric::Chebyshev),
+            "euclidean" => Some(Metric::Euclidean),
+            _ => None,
+        }
+    }
+
+    pub fn distance(&self, a: Point, b: Point) -> f64 {
+        let dx = (a.0 - b.0).abs();
+        let dy = (a.1 - b.1).abs();
+        match self {
+            Metric::Manhattan => (dx + dy) as f64,
+            Metric::Chebyshev => dx.max(dy) as f64,
+            Metric::Euclidean => (dx as f64).hypot(dy as f64),
+        }
+    }
+}
+
+/// A 2-d tree over galaxy indices, stored implicitly: each subslice of
+/// `order` has its splitting galaxy at the middle, with the axis alternating
+/// between x and y at each level.
+struct KdTree {
+    order: Vec<usize>,
+}
+
+impl KdTree {
+    fn new(points: &[Point]) -> Self {
+        let mut order: Vec<usize> = (0..points.len()).collect();
+        Self::build(&mut order, points, 0);
+        Self { order }
+    }
+
+    fn build(order: &mut [usize], points: &[Point], depth: usize) {
+        if order.len() <= 1 {
+            return;
+        }
+        let mid = order.len() / 2;
+        order.select_nth_unstable_by_key(mid, |&k| axis_value(points[k], depth));
+        let (left, right) = order.split_at_mut(mid);
+        Self::build(left, points, depth + 1);
+        Self::build(&mut right[1..], points, depth + 1);
+    }
+
+    /// The closest galaxy to `points[target]` other than itself.
+    fn nearest(&self, points: &[Point], target: usize, metric: Metric) -> Option<(usize, f64)> {
+        let mut best = None;
+        let mut stack = vec![(0, self.order.len(), 0)];
+        while let Some((start, end, depth)) = stack.pop() {
+            if start >= end {
+                continue;
+            }
+            let mid = start + (end - start) / 2;
+            let k = self.order[mid];
+            if k != target {
+                let d = metric.distance(points[target], points[k]);
+                if best.is_none_or(|(_, best_d)| d < best_d) {
+                    best = Some((k, d));
+                }
+            }
+
+            // every metric here is at least the gap along a single axis, so
+            // the far side can only help if the splitting line is close enough
+            let gap = axis_value(points[target], depth) - axis_value(points[k], depth);
+            let (near, far) = if gap < 0 {
+                ((start, mid, depth + 1), (mid + 1, end, depth + 1))
+            } else {
+                ((mid + 1, end, depth + 1), (start, mid, depth + 1))
+            };
+            if best.is_none_or(|(_, best_d)| (gap.abs() as f64) < best_d) {
+                stack.push(far);
+            }
+            stack.push(near);
+        }
+        best
+    }
+}
+
+fn axis_value(point: Point, depth: usize) -> i64 {
+    if depth.is_multiple_of(2) {
+        point.0
+    } else {
+        point.1
+    }
+}
+
+/// Distance queries over a fixed set of galaxies. Galaxies are identified by
+/// their 1-based number in reading order, as in the puzzle text.
+pub struct GalaxyQuery {
+    points: Vec<Point>,
+    tree: KdTree,
+}
+
+impl GalaxyQuery {
+    pub fn new(points: Vec<Point>) -> Self {
+        let tree = KdTree::new(&points);
+        Self { points, tree }
+    }
+
+    fn point(&self, number: usize) -> Option<Point> {
+        number
+            .checked_sub(1)
+            .and_then(|k| self.points.get(k))
+            .copied()
+    }
+
+    pub fn distance(&self, a: usize, b: usize, metric: Metric) -> Option<f64> {
+        Some(metric.distance(self.point(a)?, self.point(b)?))
+    }
+
+    /// The number of the galaxy closest to galaxy `number`, and how far away
+    /// it is.
+    pub fn nearest(&self, number: usize, metric: Metric) -> Option<(usize, f64)> {
+        self.point(number)?;
+        self.tree
+            .nearest(&self.points, number - 1, metric)
+            .map(|(k, d)| (k + 1, d))
+    }
+
+    /// The two galaxies furthest apart. Manhattan and Chebyshev only need
+    /// the extremes along a pair of axes; Euclidean compares the corners of
+    /// the convex hull.
+    pub fn farthest_pair(&self, metric: Metric) -> Option<(usize, usize, f64)> {
+        if self.points.len() < 2 {
+            return None;
+        }
+        let candidates = match metric {
+            Metric::Manhattan => vec![self.extremes(|(x, y)| x + y), self.extremes(|(x, y)| x - y)],
+            Metric::Chebyshev => vec![self.extremes(|(x, _)| x), self.extremes(|(_, y)| y)],
+            Metric::Euclidean => {
+                let hull = convex_hull(&self.points);
+                let mut pairs = vec![];
+                for (n, &a) in hull.iter().enumerate() {
+                    for &b in &hull[n + 1..] {
+                        pairs.push((a, b));
+                    }
+                }
+                pairs
+            }
+        };
+        candidates
+            .into_iter()
+            .map(|(a, b)| {
+                let d = metric.distance(self.points[a], self.points[b]);
+                (a.min(b) + 1, a.max(b) + 1, d)
+            })
+            .max_by(|x, y| x.2.total_cmp(&y.2))
+    }
+
+    /// Indices of the galaxies with the lowest and highest `key`.
+    fn extremes(&self, key: impl Fn(Point) -> i64) -> (usize, usize) {
+        let keyed = || (0..self.points.len()).map(|k| (key(self.points[k]), k));
+        let low = keyed().min().expect("no galaxies").1;
+        let high = keyed().max().expect("no galaxies").1;
+        (low, high)
+    }
+
+    /// Sum of the distances between every pair of galaxies. Manhattan sums
+    /// each axis separately; Chebyshev does the same after rotating by 45
+    /// degrees, as `max(|dx|, |dy|) = (|du| + |dv|) / 2` for `u = x + y`,
+    /// `v = x - y`. Euclidean has no such shortcut and checks every pair.
+    pub fn distance_sum(&self, metric: Metric) -> f64 {
+        match metric {
+            Metric::Manhattan => {
+                let xs = self.points.iter().map(|p| p.0).collect();
+                let ys = self.points.iter().map(|p| p.1).collect();
+                (axis_distance_sum(xs) + axis_distance_sum(ys)) as f64
+            }
+            Metric::Chebyshev => {
+                let us = self.points.iter().map(|p| p.0 + p.1).collect();
+                let vs = self.points.iter().map(|p| p.0 - p.1).collect();
+                ((axis_distance_sum(us) + axis_distance_sum(vs)) / 2) as f64
+            }
+            Metric::Euclidean => {
+                let mut total = 0.0;
+                for (n, &a) in self.points.iter().enumerate() {
+                    for &b in &self.points[n + 1..] {
+                        total += metric.distance(a, b);
+                    }
+                }
+                total
+            }
+        }
+    }
+}
+
+/// Sum of the gaps between every pair of values. Once sorted, the `k`th value
+/// is the larger of its pairs with the `k` before it, contributing
+/// `k * c - prefix`.
+pub fn axis_distance_sum(mut coords: Vec<i64>) -> i128 {
+    coords.sort_unstable();
+    let mut total = 0;
+    let mut prefix = 0;
+    for (k, c) in coords.into_iter().enumerate() {
+        total += k as i128 * c as i128 - prefix;
+        prefix += c as i128;
+    }
+    total
+}
+
+/// Indices of the points on the convex hull, by Andrew's monotone chain.
+fn convex_hull(points: &[Point]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..points.len()).collect();
+    order.sort_by_key(|&k| points[k]);
+    order.dedup_by_key(|k| points[*k]);
+    if order.len() < 3 {
+        return order;
+    }
+
+    let cross = |o: Point, a: Point, b: Point| {
+        (a.0 - o.0) as i128 * (b.1 - o.1) as i128 - (a.1 - o.1) as i128 * (b.0 - o.0) as i128
+    };
+    let mut hull: Vec<usize> = vec![];
+    for pass in [order.clone(), order.into_iter().rev().collect()] {
+        let floor = hull.len();
+        for k in pass {
+            while hull.len() >= floor + 2
+                && cross(
+                    points[hull[hull.len() - 2]],
+                    points[hull[hull.len() - 1]],
+                    points[k],
+                ) <= 0
+            {
+                hull.pop();
+            }
+            hull.push(k);
+        }
+        // the last point of each chain starts the other
+        hull.pop();
+    }
+    hull
+}
+
+#[cfg(test)]
+fn example_points() -> Vec<Point> {
+    // the puzzle's example image after expansion
+    vec![
+        (4, 0),
+        (9, 1),
+        (0, 2),
+        (8, 5),
+        (1, 6),
+        (12, 7),
+        (9, 10),
+        (0, 11),
+        (5, 11),
+    ]
+}
+
+#[test]
+fn numbered_distances() {
+    let query = GalaxyQuery::new(example_points());
+    assert_eq!(query.distance(5, 9, Metric::Manhattan), Some(9.0));
+    assert_eq!(query.distance(1, 7, Metric::Manhattan), Some(15.0));
+    assert_eq!(query.distance(3, 6, Metric::Manhattan), Some(17.0));
+    assert_eq!(query.distance(8, 9, Metric::Manhattan), Some(5.0));
+    assert_eq!(query.distance(3, 6, Metric::Chebyshev), Some(12.0));
+    assert_eq!(query.distance(8, 9, Metric::Euclidean), Some(5.0));
+    assert_eq!(query.distance(0, 9, Metric::Manhattan), None);
+    assert_eq!(query.distance(1, 10, Metric::Manhattan), None);
+}
+
+#[test]
+fn sums_match_brute_force() {
+    let points = example_points();
+    let query = GalaxyQuery::new(points.clone());
+    assert_eq!(query.distance_sum(Metric::Manhattan), 374.0);
+    for metric in [Metric::Manhattan, Metric::Chebyshev, Metric::Euclidean] {
+        let mut total = 0.0;
+        for (n, &a) in points.iter().enumerate() {
+            for &b in &points[n + 1..] {
+                total += metric.distance(a, b);
+            }
+        }
+        assert!((query.distance_sum(metric) - total).abs() < 1e-9);
+    }
+}
+
+#[test]
+fn nearest_and_farthest_match_brute_force() {
+    // a deterministic scatter, big enough for the tree to be several levels deep
+    let points: Vec<Point> = (0..500)
+        .map(|k: i64| ((k * 7919) % 1009, (k * 104_729) % 997))
+        .collect();
+    let query = GalaxyQuery::new(points.clone());
+    for metric in [Metric::Manhattan, Metric::Chebyshev, Metric::Euclidean] {
+        for number in [1, 17, 250, 500] {
+            let best = (1..=points.len())
+                .filter(|&other| other != number)
+                .map(|other| query.distance(number, other, metric).unwrap())
+                .fold(f64::INFINITY, f64::min);
+            assert_eq!(query.nearest(number, metric).unwrap().1, best);
+        }
+
+        let mut farthest: f64 = 0.0;
+        for a in 1..=points.len() {
+            for b in (a + 1)..=points.len() {
+                farthest = farthest.max(query.distance(a, b, metric).unwrap());
+            }
+        }
+        assert_eq!(query.farthest_pair(metric).unwrap().2, farthest);
+    }
+}
+
+#[test]
+fn far_apart_galaxies() {
+    // a factor of a billion puts galaxies this far apart in the puzzle input
+    let scale = 1_000_000_000_000;
+    let points: Vec<Point> = example_points()
+        .iter()
+        .map(|&(x, y)| (x * scale, y * scale))
+        .collect();
+    let query = GalaxyQuery::new(points);
+    assert_eq!(
+        query.distance(8, 9, Metric::Euclidean),
+        Some(5.0 * scale as f64)
+    );
+    let unscaled = GalaxyQuery::new(example_points());
+    let (a, b, _) = unscaled.farthest_pair(Metric::Euclidean).unwrap();
+    let (c, d, _) = query.farthest_pair(Metric::Euclidean).unwrap();
+    assert_eq!((a, b), (c, d));
+}
@@ -3,115 +3,291 @@ use std::{
     io::{prelude::*, BufReader},
 };
 
+use crate::query::{axis_distance_sum, GalaxyQuery};
+
 #[derive(Debug, Copy, Clone)]
 struct Galaxy {
     x: usize,
     y: usize,
 }
 
-pub fn space(file: File) -> (i64, i64) {
-    let reader = BufReader::new(file);
-    let mut galaxies_per_row: Vec<(usize, Option<i32>)> = vec![];
-    let mut galaxies_per_column: Vec<(usize, Option<i32>)> = vec![];
-    let mut galaxies: Vec<Galaxy> = vec![];
-
-    for (i, l) in reader
-        .lines()
-        .map(|x| x.expect("problem opening reading file!"))
-        .enumerate()
-    {
-        galaxies_per_row.push((i, None));
-        for (j, c) in l.chars().enumerate() {
-            if galaxies_per_column.len() <= j {
-                galaxies_per_column.push((j, None));
-            }
-            if c == '.' {
-                // not galaxy, ignore
-                continue;
-            }
+/// Decides how many rows or columns a line of the image becomes after
+/// expansion, given how many galaxies it holds.
+pub trait ExpansionRule {
+    fn width(&self, galaxies: usize) -> usize;
+}
 
-            // otherwise, record galaxy
-            if let Some(row_count) = galaxies_per_row[i].1 {
-                galaxies_per_row[i] = (i, Some(row_count + 1));
-            } else {
-                galaxies_per_row[i] = (i, Some(1));
-            }
+impl<F: Fn(usize) -> usize> ExpansionRule for F {
+    fn width(&self, galaxies: usize) -> usize {
+        self(galaxies)
+    }
+}
 
-            if let Some(col_count) = galaxies_per_column[j].1 {
-                galaxies_per_column[j] = (j, Some(col_count + 1));
-            } else {
-                galaxies_per_column[j] = (j, Some(1));
-            }
+/// The puzzle's rule: empty lines grow to the given width, the rest stay put.
+pub struct EmptyLines(pub usize);
 
-            galaxies.push(Galaxy { x: j, y: i });
+impl ExpansionRule for EmptyLines {
+    fn width(&self, galaxies: usize) -> usize {
+        if galaxies == 0 {
+            self.0
+        } else {
+            1
         }
     }
+}
 
-    let mut gal_copy = galaxies.clone();
-
-    expand_galaxies(
-        &mut galaxies,
-        galaxies_per_row.clone(),
-        galaxies_per_column.clone(),
-        2,
-    );
+/// Sparser lines grow more: a line with `g` galaxies becomes
+/// `1 + (factor - 1) / (g + 1)` wide, so empty lines match `EmptyLines`.
+pub struct Density(pub usize);
 
-    for gal in &gal_copy {
-        println!("{:?}", gal);
+impl ExpansionRule for Density {
+    fn width(&self, galaxies: usize) -> usize {
+        1 + (self.0 - 1) / (galaxies + 1)
     }
-    expand_galaxies(
-        &mut gal_copy,
-        galaxies_per_row,
-        galaxies_per_column,
-        1_000_000,
-    );
+}
 
-    for gal in &gal_copy {
-        println!("{:?}", gal);
+/// The distance sum under `EmptyLines`, as a linear function of the column
+/// and row factors: `base + per_column * (h - 1) + per_row * (v - 1)`.
+#[derive(Debug, PartialEq)]
+pub struct DistanceFormula {
+    pub base: i128,
+    pub per_column: i128,
+    pub per_row: i128,
+}
+
+impl DistanceFormula {
+    pub fn eval(&self, horizontal: usize, vertical: usize) -> i128 {
+        self.base
+            + self.per_column * (horizontal as i128 - 1)
+            + self.per_row * (vertical as i128 - 1)
     }
+}
 
-    return (distance_sum(&galaxies), distance_sum(&gal_copy));
+/// The parsed image: every galaxy, plus how many galaxies sit in each row
+/// and column.
+pub struct Image {
+    galaxies: Vec<Galaxy>,
+    galaxies_per_row: Vec<usize>,
+    galaxies_per_column: Vec<usize>,
 }
 
-fn expand_galaxies(
-    galaxies: &mut Vec<Galaxy>,
-    galaxies_per_row: Vec<(usize, Option<i32>)>,
-    galaxies_per_col: Vec<(usize, Option<i32>)>,
-    expansion_factor: usize,
-) {
-    for row in galaxies_per_row.iter().rev() {
-        if let Some(_) = row.1 {
-            // there are galaxies here, no expansion!
-            continue;
-        } else {
-            for gal in &mut *galaxies {
-                if gal.y >= row.0 {
-                    gal.y += expansion_factor - 1;
+impl Image {
+    pub fn read(file: File) -> Self {
+        let reader = BufReader::new(file);
+        Self::from_lines(
+            reader
+                .lines()
+                .map(|x| x.expect("problem opening reading file!")),
+        )
+    }
+
+    fn from_lines(lines: impl Iterator<Item = String>) -> Self {
+        let mut galaxies_per_row: Vec<usize> = vec![];
+        let mut galaxies_per_column: Vec<usize> = vec![];
+        let mut galaxies: Vec<Galaxy> = vec![];
+
+        for (i, l) in lines.enumerate() {
+            galaxies_per_row.push(0);
+            for (j, c) in l.chars().enumerate() {
+                if galaxies_per_column.len() <= j {
+                    galaxies_per_column.push(0);
+                }
+                if c == '.' {
+                    // not galaxy, ignore
+                    continue;
                 }
+
+                // otherwise, record galaxy
+                galaxies_per_row[i] += 1;
+                galaxies_per_column[j] += 1;
+                galaxies.push(Galaxy { x: j, y: i });
             }
         }
+
+        Self {
+            galaxies,
+            galaxies_per_row,
+            galaxies_per_column,
+        }
     }
 
-    for col in galaxies_per_col.iter().rev() {
-        if let Some(_) = col.1 {
-            continue;
-        } else {
-            for gal in &mut *galaxies {
-                if gal.x >= col.0 {
-                    gal.x += expansion_factor - 1;
-                }
-            }
+    /// Galaxy positions as `(x, y)`, in the order the puzzle numbers them.
+    pub fn galaxies(&self) -> Vec<(usize, usize)> {
+        self.galaxies.iter().map(|gal| (gal.x, gal.y)).collect()
+    }
+
+    pub fn empty_rows(&self) -> Vec<usize> {
+        empty_lines(&self.galaxies_per_row)
+    }
+
+    pub fn empty_columns(&self) -> Vec<usize> {
+        empty_lines(&self.galaxies_per_column)
+    }
+
+    /// The galaxies after each column is widened by `horizontal` and each
+    /// row by `vertical`.
+    fn expand_galaxies(
+        &self,
+        horizontal: &impl ExpansionRule,
+        vertical: &impl ExpansionRule,
+    ) -> Vec<Galaxy> {
+        let rows = expanded_offsets(&self.galaxies_per_row, vertical);
+        let cols = expanded_offsets(&self.galaxies_per_column, horizontal);
+        self.galaxies
+            .iter()
+            .map(|gal| Galaxy {
+                x: cols[gal.x],
+                y: rows[gal.y],
+            })
+            .collect()
+    }
+
+    pub fn distance_sum(
+        &self,
+        horizontal: &impl ExpansionRule,
+        vertical: &impl ExpansionRule,
+    ) -> i128 {
+        distance_sum(&self.expand_galaxies(horizontal, vertical))
+    }
+
+    /// Distance queries over the galaxies after expansion.
+    pub fn query(
+        &self,
+        horizontal: &impl ExpansionRule,
+        vertical: &impl ExpansionRule,
+    ) -> GalaxyQuery {
+        let points = self
+            .expand_galaxies(horizontal, vertical)
+            .iter()
+            .map(|gal| (gal.x as i64, gal.y as i64))
+            .collect();
+        GalaxyQuery::new(points)
+    }
+
+    /// Every empty column (row) between a pair adds `h - 1` (`v - 1`) to its
+    /// distance, so two extra sums pin down how many of each are crossed.
+    pub fn distance_formula(&self) -> DistanceFormula {
+        let base = self.distance_sum(&EmptyLines(1), &EmptyLines(1));
+        DistanceFormula {
+            base,
+            per_column: self.distance_sum(&EmptyLines(2), &EmptyLines(1)) - base,
+            per_row: self.distance_sum(&EmptyLines(1), &EmptyLines(2)) - base,
         }
     }
 }
 
-fn distance_sum(galaxies: &Vec<Galaxy>) -> i64 {
-    let mut total = 0;
-    for i in 0..(galaxies.len() - 1) {
-        for j in (i + 1)..galaxies.len() {
-            total += (galaxies[i].x as i64 - galaxies[j].x as i64).abs()
-                + (galaxies[i].y as i64 - galaxies[j].y as i64).abs();
+fn empty_lines(galaxies_per_line: &[usize]) -> Vec<usize> {
+    (0..galaxies_per_line.len())
+        .filter(|&k| galaxies_per_line[k] == 0)
+        .collect()
+}
+
+/// Maps each original row (or column) index to its index after expansion.
+fn expanded_offsets(galaxies_per_line: &[usize], rule: &impl ExpansionRule) -> Vec<usize> {
+    let mut offsets = Vec::with_capacity(galaxies_per_line.len());
+    let mut position = 0;
+    for &count in galaxies_per_line {
+        offsets.push(position);
+        position += rule.width(count);
+    }
+    offsets
+}
+
+/// The distance sum for each `(horizontal, vertical)` pair of factors. Under
+/// the puzzle's empty-line rule this also returns the formula behind them;
+/// with `density` the rule isn't linear, so each sum is worked out in full.
+pub fn space(
+    image: &Image,
+    factors: &[(usize, usize)],
+    density: bool,
+) -> (Option<DistanceFormula>, Vec<i128>) {
+    if density {
+        let sums = factors
+            .iter()
+            .map(|&(h, v)| image.distance_sum(&Density(h), &Density(v)))
+            .collect();
+        return (None, sums);
+    }
+
+    let formula = image.distance_formula();
+    let sums = factors.iter().map(|&(h, v)| formula.eval(h, v)).collect();
+    (Some(formula), sums)
+}
+
+/// Sum of the Manhattan distances between every pair of galaxies, taking
+/// each axis separately.
+fn distance_sum(galaxies: &[Galaxy]) -> i128 {
+    axis_distance_sum(galaxies.iter().map(|gal| gal.x as i64).collect())
+        + axis_distance_sum(galaxies.iter().map(|gal| gal.y as i64).collect())
+}
+
+#[cfg(test)]
+fn test_image() -> Image {
+    let lines = [
+        "...#......",
+        ".......#..",
+        "#.........",
+        "..........",
+        "......#...",
+        ".#........",
+        ".........#",
+        "..........",
+        ".......#..",
+        "#...#.....",
+    ];
+    Image::from_lines(lines.iter().map(|l| l.to_string()))
+}
+
+#[test]
+fn example_distances() {
+    let image = test_image();
+    for (factor, expected) in [(2, 374), (10, 1030), (100, 8410)] {
+        let rule = EmptyLines(factor);
+        assert_eq!(image.distance_sum(&rule, &rule), expected);
+    }
+}
+
+#[test]
+fn empty_lines_found() {
+    let image = test_image();
+    assert_eq!(image.empty_rows(), vec![3, 7]);
+    assert_eq!(image.empty_columns(), vec![2, 5, 8]);
+    assert_eq!(image.galaxies()[0], (3, 0));
+}
+
+#[test]
+fn custom_rules() {
+    let image = test_image();
+    let unexpanded = image.distance_sum(&EmptyLines(1), &EmptyLines(1));
+    // doubling every line doubles every distance
+    assert_eq!(image.distance_sum(&|_| 2, &|_| 2), 2 * unexpanded);
+    assert_eq!(image.distance_sum(&Density(1), &Density(1)), unexpanded);
+    // no line holds more than two galaxies, so these only differ from the
+    // puzzle rule once the factor is large enough to widen occupied lines
+    assert_eq!(image.distance_sum(&Density(2), &Density(2)), 374);
+    assert!(image.distance_sum(&Density(10), &Density(10)) > 1030);
+}
+
+#[test]
+fn formula_matches_pairwise_sum() {
+    let image = test_image();
+    let formula = image.distance_formula();
+    for (h, v) in [(1, 1), (2, 2), (10, 1), (1, 100), (7, 1_000_000)] {
+        let galaxies = image.expand_galaxies(&EmptyLines(h), &EmptyLines(v));
+        let mut pairwise = 0;
+        for i in 0..galaxies.len() {
+            for j in (i + 1)..galaxies.len() {
+                pairwise += galaxies[i].x.abs_diff(galaxies[j].x) as i128
+                    + galaxies[i].y.abs_diff(galaxies[j].y) as i128;
+            }
         }
+        assert_eq!(formula.eval(h, v), pairwise);
     }
-    return total;
+}
+
+#[test]
+fn huge_factors() {
+    // 374 at a factor of 2 and 1030 at 10 make the example 292 + 82 * (f - 1)
+    let (_, sums) = space(&test_image(), &[(usize::MAX, usize::MAX)], false);
+    assert_eq!(sums[0], 292 + 82 * (usize::MAX as i128 - 1));
 }
@@ -1,16 +1,24 @@
 use std::{env, path::Path};
 
 mod reflection;
-use reflection::reflection;
+use reflection::{read_patterns, reflection, smudged_reflections, Orientation, Smudges};
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let (flags, args): (Vec<String>, Vec<String>) =
+        env::args().partition(|arg| arg.starts_with("--"));
+    let mut render = false;
+    for flag in &flags {
+        match flag.as_str() {
+            "--render" => render = true,
+            _ => panic!("unknown flag '{}'", flag),
+        }
+    }
 
     match args.len() {
         1 => {
             panic!("not enough args!");
         }
-        2 => {}
+        2 | 3 => {}
         _ => {
             panic!("too many args!");
         }
@@ -19,8 +27,112 @@ fn main() {
     let filepath = &args[1];
     let path = Path::new(filepath);
 
-    let (pt1, pt2) = reflection(&path);
+    let k = args
+        .get(2)
+        .map(|k| k.parse().expect("smudge count must be a number!"));
+    if render {
+        print_mirrors(path, k.unwrap_or(0));
+    }
+    if let Some(k) = k {
+        print_smudges(path, k);
+        return;
+    }
+
+    let (pt1, pt2) = reflection(path);
 
     println!("pt1: {}", pt1);
     println!("pt2: {}", pt2);
 }
+
+fn print_smudges(path: &Path, k: u32) {
+    let mut total = 0;
+    for (i, reflections) in smudged_reflections(path, k).iter().enumerate() {
+        for r in reflections {
+            let line = match r.orientation {
+                Orientation::Horizontal => "row",
+                Orientation::Vertical => "column",
+            };
+            let smudges: Vec<String> = r
+                .smudges
+                .iter()
+                .map(|(a, b)| format!("{:?}/{:?}", a, b))
+                .collect();
+            println!(
+                "pattern {}: mirror after {} {} reflecting {} pairs, smudges [{}]",
+                i + 1,
+                line,
+                r.index + 1,
+                r.pairs,
+                smudges.join(", ")
+            );
+            total += r.score();
+        }
+    }
+    println!("total with {} smudges: {}", k, total);
+}
+
+fn print_mirrors(path: &Path, k: u32) {
+    for (i, pattern) in read_patterns(path).iter().enumerate() {
+        let reflections = pattern.reflections(&Smudges(k));
+        println!("pattern {}:", i + 1);
+        if reflections.is_empty() {
+            println!("{}", pattern.render(None));
+        }
+        for r in &reflections {
+            println!("{}", pattern.render(Some(r)));
+        }
+    }
+}
+
+#[test]
+fn custom_matchers() {
+    use reflection::{Exact, Line, Pattern, ReflectionMatcher};
+
+    // up to `k` differences in total, rather than exactly `k`
+    struct Tolerance(u32);
+
+    impl ReflectionMatcher for Tolerance {
+        type State = u32;
+
+        fn step(&self, differences: &mut u32, a: &Line, b: &Line) -> bool {
+            *differences += a.difference(b);
+            *differences <= self.0
+        }
+
+        fn accept(&self, _: &u32) -> bool {
+            true
+        }
+    }
+
+    // each line reflects onto the other end to end, so the pattern looks the
+    // same turned half way round about the middle of the mirror
+    struct Turned;
+
+    impl ReflectionMatcher for Turned {
+        type State = ();
+
+        fn step(&self, _: &mut (), a: &Line, b: &Line) -> bool {
+            a.len() == b.len() && (0..a.len()).all(|i| a.get(i) == b.get(b.len() - 1 - i))
+        }
+
+        fn accept(&self, _: &()) -> bool {
+            true
+        }
+    }
+
+    let first = Pattern::from(
+        "#.##..##.\n..#.##.#.\n##......#\n##......#\n..#.##.#.\n..##..##.\n#.#.##.#.".to_string(),
+    );
+    let exact = first.reflections(&Exact);
+    let smudged = first.reflections(&Smudges(1));
+    let tolerant = first.reflections(&Tolerance(1));
+    assert_eq!(tolerant.len(), exact.len() + smudged.len());
+    assert!(tolerant.contains(&exact[0]) && tolerant.contains(&smudged[0]));
+
+    let turned = Pattern::from("#..\n..#".to_string()).reflections(&Turned);
+    assert_eq!(turned.len(), 1);
+    assert_eq!(
+        (turned[0].orientation, turned[0].index),
+        (Orientation::Horizontal, 0)
+    );
+}
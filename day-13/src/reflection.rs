@@ -1,14 +1,146 @@
-use std::{fs::read_to_string, iter::zip, path::Path, vec};
+use std::{fs::read_to_string, iter::zip, path::Path};
+
+/// One row or column of a pattern as a bitset, with a bit set for each `#`.
+/// Cell `i` lives in bit `i % 64` of word `i / 64`, so lines of any length
+/// compare a word at a time.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Line {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl Line {
+    pub fn from(chars: &[char]) -> Self {
+        let mut words = vec![0; chars.len().div_ceil(64)];
+        for (i, &c) in chars.iter().enumerate() {
+            if c == '#' {
+                words[i / 64] |= 1 << (i % 64);
+            }
+        }
+        Self {
+            words,
+            len: chars.len(),
+        }
+    }
+
+    /// The number of cells in the line.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The number of cells that differ between two lines of the same length.
+    pub fn difference(&self, other: &Line) -> u32 {
+        debug_assert_eq!(self.len(), other.len());
+        zip(&self.words, &other.words)
+            .map(|(x, y)| (x ^ y).count_ones())
+            .sum()
+    }
+
+    /// Whether cell `i` is a `#`.
+    pub fn get(&self, i: usize) -> bool {
+        self.words[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    /// The positions of the cells that differ between two lines.
+    pub fn differing_cells(&self, other: &Line) -> Vec<usize> {
+        let mut cells = vec![];
+        for (w, (x, y)) in zip(&self.words, &other.words).enumerate() {
+            let mut diff = x ^ y;
+            while diff != 0 {
+                cells.push(w * 64 + diff.trailing_zeros() as usize);
+                diff &= diff - 1;
+            }
+        }
+        cells
+    }
+}
+
+/// Which way a mirror runs: `Horizontal` mirrors lie between two rows,
+/// `Vertical` ones between two columns.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Orientation {
+    Horizontal,
+    Vertical,
+}
+
+/// A mirror line after row or column `index`, reflecting `pairs` pairs of
+/// lines onto each other before one side runs out, along with the smudged
+/// cells that had to be fixed for it to reflect perfectly. Each smudge is
+/// given as a `(row, col)` cell and the cell it mirrors onto.
+#[derive(Debug, PartialEq)]
+pub struct Reflection {
+    pub orientation: Orientation,
+    pub index: usize,
+    pub pairs: usize,
+    pub smudges: Vec<((usize, usize), (usize, usize))>,
+}
+
+impl Reflection {
+    /// The puzzle's summary: columns to the left of a vertical mirror, or
+    /// 100 times the rows above a horizontal one.
+    pub fn score(&self) -> u32 {
+        match self.orientation {
+            Orientation::Horizontal => (self.index as u32 + 1) * 100,
+            Orientation::Vertical => self.index as u32 + 1,
+        }
+    }
+}
+
+/// Decides whether a candidate mirror reflects, looking at one mirrored pair
+/// of lines at a time. Implementations are picked at compile time, so the
+/// per-pair check can be inlined into the search.
+pub trait ReflectionMatcher {
+    /// Whatever needs carrying between the pairs of a single mirror.
+    type State: Default;
+
+    /// Takes in the next pair out from the mirror, returning `false` as soon
+    /// as the mirror can be ruled out.
+    fn step(&self, state: &mut Self::State, a: &Line, b: &Line) -> bool;
+
+    /// Whether the mirror reflects, once every pair has been seen.
+    fn accept(&self, state: &Self::State) -> bool;
+}
+
+/// Every mirrored pair of lines must be identical.
+pub struct Exact;
+
+impl ReflectionMatcher for Exact {
+    type State = ();
+
+    fn step(&self, _: &mut (), a: &Line, b: &Line) -> bool {
+        a == b
+    }
+
+    fn accept(&self, _: &()) -> bool {
+        true
+    }
+}
+
+/// Exactly this many cells, summed over all the mirrored pairs, must differ
+/// from their reflection.
+pub struct Smudges(pub u32);
+
+impl ReflectionMatcher for Smudges {
+    type State = u32;
+
+    fn step(&self, differences: &mut u32, a: &Line, b: &Line) -> bool {
+        *differences += a.difference(b);
+        *differences <= self.0
+    }
+
+    fn accept(&self, differences: &u32) -> bool {
+        *differences == self.0
+    }
+}
 
 #[derive(Debug)]
-struct Pattern {
-    rows: Vec<u32>,
-    cols: Vec<u32>,
-    pattern: String,
+pub struct Pattern {
+    rows: Vec<Line>,
+    cols: Vec<Line>,
 }
 
 impl Pattern {
-    fn from(pattern: String) -> Self {
+    pub fn from(pattern: String) -> Self {
         let pattern_rows = pattern.split('\n').filter(|x| x != &"");
         let mut rows = vec![];
 
@@ -17,155 +149,315 @@ impl Pattern {
         for row in pattern_rows {
             for (i, c) in row.chars().enumerate() {
                 if temp.len() <= i {
-                    let mut new_vec = vec![];
-                    new_vec.push(c);
-                    temp.push(new_vec);
+                    temp.push(vec![c]);
                 } else {
                     temp[i].push(c);
                 }
             }
-            rows.push(Self::get_encoding(row.chars().collect()));
+            rows.push(Line::from(&row.chars().collect::<Vec<char>>()));
         }
 
-        let cols = temp.into_iter().map(|x| Self::get_encoding(x)).collect();
+        let cols = temp.iter().map(|x| Line::from(x)).collect();
 
-        return Self {
-            rows,
-            cols,
-            pattern,
-        };
+        Self { rows, cols }
+    }
+
+    /// Every mirror line, horizontal then vertical, that `matcher` accepts.
+    pub fn reflections<M: ReflectionMatcher>(&self, matcher: &M) -> Vec<Reflection> {
+        let mut reflections = vec![];
+        for (orientation, lines) in self.orientations() {
+            for index in Self::reflection_indices(lines, matcher) {
+                reflections.push(self.reflection_at(orientation, index));
+            }
+        }
+        reflections
     }
 
-    fn get_encoding(chars: Vec<char>) -> u32 {
-        chars
-            .into_iter()
-            .fold(0, |acc, c| (acc << 1) + if c == '#' { 1 } else { 0 })
+    fn orientations(&self) -> [(Orientation, &Vec<Line>); 2] {
+        [
+            (Orientation::Horizontal, &self.rows),
+            (Orientation::Vertical, &self.cols),
+        ]
     }
 
-    pub fn get_total(&self, comparator: &Box<dyn Fn(&u32, &u32) -> bool>) -> u32 {
-        if let Some(index) = Self::reflection_index(&self.rows, comparator) {
-            return (index as u32 + 1) * 100;
-        }
-        if let Some(index) = Self::reflection_index(&self.cols, comparator) {
-            return (index as u32) + 1;
+    /// Describes the mirror after line `index`, finding any smudges on it.
+    fn reflection_at(&self, orientation: Orientation, index: usize) -> Reflection {
+        let lines = match orientation {
+            Orientation::Horizontal => &self.rows,
+            Orientation::Vertical => &self.cols,
+        };
+        let smudges = Self::mirrored_pairs(lines, index)
+            .flat_map(|(a, b)| {
+                lines[a]
+                    .differing_cells(&lines[b])
+                    .into_iter()
+                    .map(move |cell| match orientation {
+                        Orientation::Horizontal => ((a, cell), (b, cell)),
+                        Orientation::Vertical => ((cell, a), (cell, b)),
+                    })
+            })
+            .collect();
+        Reflection {
+            orientation,
+            index,
+            pairs: Self::mirrored_pairs(lines, index).count(),
+            smudges,
         }
+    }
 
-        return 0;
-    }
-
-    pub fn get_smudge_total(
-        &self,
-        smudge_comparator: &Box<dyn Fn(&u32, &u32, bool) -> (bool, bool)>,
-    ) -> u32 {
-        let default_comparator: Box<dyn Fn(&u32, &u32) -> bool> = Box::new(comp);
-        let matches = (
-            Self::reflection_index(&self.rows, &default_comparator),
-            Self::reflection_index(&self.cols, &default_comparator),
-        );
-        if let Some(index) = Self::smudge_reflection_index(&self.rows, smudge_comparator, matches.0)
-        {
-            return (index as u32 + 1) * 100;
-        }
-        if let Some(index) = Self::smudge_reflection_index(&self.cols, smudge_comparator, matches.1)
-        {
-            return (index as u32) + 1;
-        }
+    /// The pairs of line indices reflected onto each other by a mirror after
+    /// line `index`, working outwards from the mirror.
+    fn mirrored_pairs(lines: &[Line], index: usize) -> impl Iterator<Item = (usize, usize)> {
+        zip((0..=index).rev(), (index + 1)..lines.len())
+    }
 
-        return 0;
-    }
-
-    /// Returns the index after which the reflection occurs, if there is a
-    /// reflection.
-    fn reflection_index(
-        encoding: &Vec<u32>,
-        comparator: &Box<dyn Fn(&u32, &u32) -> bool>,
-    ) -> Option<usize> {
-        for i in 0..(encoding.len() - 1) {
-            if zip(
-                encoding[0..(i + 1)].iter().rev(),
-                encoding[(i + 1)..].iter(),
-            )
-            .all(|(x, y)| comparator.as_ref()(x, y))
-            {
-                // true when there is a horizontal reflection after index i
-                return Some(i);
-            }
-        }
+    /// Returns every index after which a reflection occurs, feeding the
+    /// mirrored pairs to `matcher` from the mirror outwards.
+    fn reflection_indices<M: ReflectionMatcher>(encoding: &[Line], matcher: &M) -> Vec<usize> {
+        (0..encoding.len().saturating_sub(1))
+            .filter(|&i| {
+                let mut state = M::State::default();
+                Self::mirrored_pairs(encoding, i)
+                    .all(|(a, b)| matcher.step(&mut state, &encoding[a], &encoding[b]))
+                    && matcher.accept(&state)
+            })
+            .collect()
+    }
 
-        return None;
-    }
-
-    /// Returns the index after which the reflection occurs, if there is a
-    /// reflection.
-    fn smudge_reflection_index(
-        encoding: &Vec<u32>,
-        comparator: &Box<dyn Fn(&u32, &u32, bool) -> (bool, bool)>,
-        ignored_index: Option<usize>,
-    ) -> Option<usize> {
-        for i in 0..(encoding.len() - 1) {
-            if zip(
-                encoding[0..(i + 1)].iter().rev(),
-                encoding[(i + 1)..].iter(),
-            )
-            .fold((true, false), |acc, x| {
-                let (match_found, smudge_found) = comparator.as_ref()(x.0, x.1, acc.1);
-                return (acc.0 && match_found, smudge_found);
+    /// Draws the pattern as in the puzzle text, with the mirror line marked
+    /// by `><` above and below a vertical mirror, or `v^` either side of a
+    /// horizontal one.
+    pub fn render(&self, reflection: Option<&Reflection>) -> String {
+        let (height, width) = (self.rows.len(), self.cols.len());
+        let cells: Vec<String> = self
+            .rows
+            .iter()
+            .map(|row| {
+                (0..width)
+                    .map(|c| if row.get(c) { '#' } else { '.' })
+                    .collect()
             })
-            .0
-            {
-                if let Some(ignored) = ignored_index {
-                    if ignored == i {
-                        continue;
-                    }
+            .collect();
+
+        let mut out = String::new();
+        match reflection {
+            Some(r) if r.orientation == Orientation::Vertical => {
+                let numbers: String = (1..=width)
+                    .map(|c| char::from_digit((c % 10) as u32, 10).unwrap())
+                    .collect();
+                let marker = format!(
+                    "{}><{}",
+                    " ".repeat(r.index),
+                    " ".repeat(width - r.index - 2)
+                );
+                out.push_str(&format!("{}\n{}\n", numbers, marker));
+                for row in &cells {
+                    out.push_str(&format!("{}\n", row));
+                }
+                out.push_str(&format!("{}\n{}\n", marker, numbers));
+            }
+            Some(r) => {
+                let digits = height.to_string().len();
+                for (i, row) in cells.iter().enumerate() {
+                    let marker = if i == r.index {
+                        'v'
+                    } else if i == r.index + 1 {
+                        '^'
+                    } else {
+                        ' '
+                    };
+                    out.push_str(&format!(
+                        "{:>w$}{m}{}{m}{}\n",
+                        i + 1,
+                        row,
+                        i + 1,
+                        w = digits,
+                        m = marker
+                    ));
+                }
+            }
+            None => {
+                for row in &cells {
+                    out.push_str(&format!("{}\n", row));
                 }
-                // true when there is a horizontal reflection after index i
-                return Some(i);
             }
         }
+        out
+    }
+}
 
-        return None;
+/// Complains about patterns that don't have exactly one mirror, since the
+/// puzzle's summary is only meaningful when there is a single one.
+fn check_reflections(number: usize, smudges: u32, reflections: &[Reflection]) {
+    match reflections.len() {
+        0 => eprintln!(
+            "warning: pattern {} has no reflection with {} smudges",
+            number, smudges
+        ),
+        1 => {}
+        n => eprintln!(
+            "warning: pattern {} has {} reflections with {} smudges",
+            number, n, smudges
+        ),
     }
 }
 
-pub fn reflection(path: &Path) -> (u32, u32) {
-    let patterns: Vec<Pattern> = read_to_string(path)
+pub fn read_patterns(path: &Path) -> Vec<Pattern> {
+    read_to_string(path)
         .expect("error reading file!")
         .split("\n\n")
         .map(|x| Pattern::from(x.to_string()))
-        .collect();
+        .collect()
+}
 
-    let pt1_comparator: Box<dyn Fn(&u32, &u32) -> bool> = Box::new(comp);
-    let pt2_comparator: Box<dyn Fn(&u32, &u32, bool) -> (bool, bool)> = Box::new(smudge_comp);
+pub fn reflection(path: &Path) -> (u32, u32) {
+    let patterns = read_patterns(path);
 
-    let pt1 = patterns
-        .iter()
-        .map(|x| x.get_total(&pt1_comparator))
-        .fold(0, |acc, e| acc + e);
+    let mut pt1 = 0;
+    let mut pt2 = 0;
+    for (i, pattern) in patterns.iter().enumerate() {
+        let reflections = pattern.reflections(&Exact);
+        check_reflections(i + 1, 0, &reflections);
+        pt1 += reflections.iter().map(|r| r.score()).sum::<u32>();
 
-    let pt2 = patterns
-        .iter()
-        .map(|x| x.get_smudge_total(&pt2_comparator))
-        .fold(0, |acc, e| acc + e);
+        let reflections = pattern.reflections(&Smudges(1));
+        check_reflections(i + 1, 1, &reflections);
+        pt2 += reflections.iter().map(|r| r.score()).sum::<u32>();
+    }
 
-    return (pt1, pt2);
+    (pt1, pt2)
 }
 
-fn comp(x: &u32, y: &u32) -> bool {
-    x == y
+/// The reflections with exactly `k` smudges in each pattern of the file.
+pub fn smudged_reflections(path: &Path, k: u32) -> Vec<Vec<Reflection>> {
+    read_patterns(path)
+        .iter()
+        .map(|x| x.reflections(&Smudges(k)))
+        .collect()
 }
 
-fn smudge_comp(x: &u32, y: &u32, smudge_found: bool) -> (bool, bool) {
-    if x == y {
-        return (true, smudge_found);
-    }
-    if smudge_found {
-        return (false, smudge_found);
+#[cfg(test)]
+fn total(pattern: &Pattern) -> u32 {
+    pattern.reflections(&Exact).iter().map(|r| r.score()).sum()
+}
+
+#[cfg(test)]
+fn wide_pattern(width: usize, mirror_after: usize) -> String {
+    // five rows of scattered cells, mirrored only about `mirror_after`
+    let mut rows = vec![];
+    for r in 0..5 {
+        let row: String = (0..width)
+            .map(|c| {
+                let reflected = if c <= mirror_after {
+                    c
+                } else {
+                    2 * mirror_after + 1 - c
+                };
+                let hash = (reflected * 2_654_435_761 + r * 40_503) % 1_000_003;
+                if hash % 7 < 3 {
+                    '#'
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        rows.push(row);
     }
+    rows.join("\n")
+}
 
-    let xor = x ^ y;
-    if xor == 1 || (xor & (xor - 1) == 0) {
-        return (true, true);
+#[test]
+fn wide_patterns() {
+    // rows this wide used to overflow a u32 encoding
+    let pattern = Pattern::from(wide_pattern(45, 39));
+    assert_eq!(pattern.cols.len(), 45);
+    assert_eq!(total(&pattern), 40);
+
+    let pattern = Pattern::from(wide_pattern(130, 100));
+    assert_eq!(total(&pattern), 101);
+
+    // and the same again on its side, so the columns are the long lines
+    let wide = wide_pattern(45, 39);
+    let rows: Vec<Vec<char>> = wide.lines().map(|r| r.chars().collect()).collect();
+    let tall: Vec<String> = (0..45)
+        .map(|c| rows.iter().map(|r| r[c]).collect())
+        .collect();
+    let pattern = Pattern::from(tall.join("\n"));
+    assert_eq!(pattern.rows.len(), 45);
+    assert_eq!(total(&pattern), 4000);
+}
+
+#[test]
+fn line_difference() {
+    let mut chars = vec!['.'; 100];
+    let a = Line::from(&chars);
+    chars[3] = '#';
+    chars[70] = '#';
+    let b = Line::from(&chars);
+    assert_eq!(a.difference(&b), 2);
+    assert_eq!(b.difference(&b), 0);
+    assert_ne!(a, b);
+}
+
+#[test]
+fn example_smudges() {
+    let first = Pattern::from(
+        "#.##..##.\n..#.##.#.\n##......#\n##......#\n..#.##.#.\n..##..##.\n#.#.##.#.".to_string(),
+    );
+    assert_eq!(
+        first.reflections(&Smudges(0)),
+        vec![Reflection {
+            orientation: Orientation::Vertical,
+            index: 4,
+            pairs: 4,
+            smudges: vec![],
+        }]
+    );
+    // fixing the top-left cell moves the mirror between rows 3 and 4
+    assert_eq!(
+        first.reflections(&Smudges(1)),
+        vec![Reflection {
+            orientation: Orientation::Horizontal,
+            index: 2,
+            pairs: 3,
+            smudges: vec![((0, 0), (5, 0))],
+        }]
+    );
+
+    let second = Pattern::from(
+        "#...##..#\n#....#..#\n..##..###\n#####.##.\n#####.##.\n..##..###\n#....#..#".to_string(),
+    );
+    assert_eq!(second.reflections(&Smudges(1))[0].score(), 100);
+    assert_eq!(
+        second.reflections(&Smudges(1))[0].smudges,
+        vec![((0, 4), (1, 4))]
+    );
+
+    // with two smudges allowed, every reflection must use both
+    for reflection in second.reflections(&Smudges(2)) {
+        assert_eq!(reflection.smudges.len(), 2);
     }
+}
+
+#[test]
+fn render_mirrors() {
+    let first = Pattern::from(
+        "#.##..##.\n..#.##.#.\n##......#\n##......#\n..#.##.#.\n..##..##.\n#.#.##.#.".to_string(),
+    );
+    let reflections = first.reflections(&Exact);
+    assert_eq!(reflections.len(), 1);
+    assert_eq!(
+        first.render(Some(&reflections[0])),
+        "123456789\n    ><   \n#.##..##.\n..#.##.#.\n##......#\n##......#\n..#.##.#.\n..##..##.\n#.#.##.#.\n    ><   \n123456789\n"
+    );
 
-    (false, smudge_found)
+    let second = Pattern::from(
+        "#...##..#\n#....#..#\n..##..###\n#####.##.\n#####.##.\n..##..###\n#....#..#".to_string(),
+    );
+    let reflections = second.reflections(&Exact);
+    assert_eq!(reflections[0].pairs, 3);
+    assert_eq!(
+        second.render(Some(&reflections[0])),
+        "1 #...##..# 1\n2 #....#..# 2\n3 ..##..### 3\n4v#####.##.v4\n5^#####.##.^5\n6 ..##..### 6\n7 #....#..# 7\n"
+    );
 }
@@ -0,0 +1,102 @@
+use std::fmt;
+
+use crate::tilt::ShiftType;
+
+/// A run of tilts applied `repeats` times over, written like `NWSE*1000`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Step {
+    pub tilts: Vec<ShiftType>,
+    pub repeats: usize,
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for tilt in &self.tilts {
+            write!(f, "{}", tilt.letter())?;
+        }
+        if self.repeats != 1 {
+            write!(f, "*{}", self.repeats)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CommandError {
+    EmptyStep,
+    UnknownDirection(char),
+    BadCount(String),
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandError::EmptyStep => write!(f, "step has no tilts"),
+            CommandError::UnknownDirection(c) => {
+                write!(f, "unknown direction '{}', expected N, W, S or E", c)
+            }
+            CommandError::BadCount(count) => write!(f, "bad repeat count '{}'", count),
+        }
+    }
+}
+
+/// Parses comma-separated steps of `NWSE` tilts, each with an optional `*count`.
+pub fn parse(program: &str) -> Result<Vec<Step>, CommandError> {
+    program
+        .split(',')
+        .map(|step| {
+            let step = step.trim();
+            let (tilts, repeats) = match step.split_once('*') {
+                Some((tilts, count)) => {
+                    let count = count.trim();
+                    let repeats = count
+                        .parse()
+                        .map_err(|_| CommandError::BadCount(count.to_string()))?;
+                    (tilts.trim(), repeats)
+                }
+                None => (step, 1),
+            };
+            if tilts.is_empty() {
+                return Err(CommandError::EmptyStep);
+            }
+            let tilts = tilts
+                .chars()
+                .map(|c| ShiftType::from(c).ok_or(CommandError::UnknownDirection(c)))
+                .collect::<Result<_, _>>()?;
+            Ok(Step { tilts, repeats })
+        })
+        .collect()
+}
+
+#[test]
+fn parse_programs() {
+    use ShiftType::*;
+    assert_eq!(
+        parse("NWSE*1000000000"),
+        Ok(vec![Step {
+            tilts: vec![Up, Left, Down, Right],
+            repeats: 1_000_000_000
+        }])
+    );
+    assert_eq!(
+        parse("E, e ,S*2"),
+        Ok(vec![
+            Step {
+                tilts: vec![Right],
+                repeats: 1
+            },
+            Step {
+                tilts: vec![Right],
+                repeats: 1
+            },
+            Step {
+                tilts: vec![Down],
+                repeats: 2
+            },
+        ])
+    );
+    assert_eq!(parse("N,,S"), Err(CommandError::EmptyStep));
+    assert_eq!(parse("NX"), Err(CommandError::UnknownDirection('X')));
+    assert_eq!(parse("N*lots"), Err(CommandError::BadCount("lots".into())));
+    assert_eq!(parse("WS*3").unwrap()[0].to_string(), "WS*3");
+}
@@ -1,15 +1,44 @@
-use std::{env, path::Path};
+use std::{env, path::Path, time::Duration};
 
+mod command;
 mod tilt;
+use tilt::{Animation, Panel, ShiftType};
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let (flags, args): (Vec<String>, Vec<String>) =
+        env::args().partition(|arg| arg.starts_with("--"));
+    let mut edge = ShiftType::Up;
+    let mut render = false;
+    let mut diff = false;
+    let mut animate = false;
+    let mut colour = false;
+    let mut delay = Duration::from_millis(200);
+    for flag in &flags {
+        match flag.split_once('=') {
+            Some(("--load", letter)) => {
+                let mut chars = letter.chars();
+                edge = match (chars.next().and_then(ShiftType::from), chars.next()) {
+                    (Some(edge), None) => edge,
+                    _ => panic!("load edge must be one of N, W, S or E!"),
+                };
+            }
+            Some(("--delay", ms)) => {
+                animate = true;
+                delay = Duration::from_millis(ms.parse().expect("delay must be a number!"));
+            }
+            None if flag == "--render" => render = true,
+            None if flag == "--diff" => diff = true,
+            None if flag == "--animate" => animate = true,
+            None if flag == "--colour" => colour = true,
+            _ => panic!("unknown flag '{}'", flag),
+        }
+    }
 
     match args.len() {
         1 => {
             panic!("not enough args!");
         }
-        2 => {}
+        2 | 3 => {}
         _ => {
             panic!("too many args!");
         }
@@ -18,8 +47,44 @@ fn main() {
     let filepath = &args[1];
     let path = Path::new(filepath);
 
-    let (pt1, pt2) = tilt::tilt(&path);
+    // a bare number is a count of spin cycles
+    let program = match args.get(2) {
+        Some(arg) if arg.parse::<usize>().is_ok() => format!("NWSE*{}", arg),
+        Some(arg) => arg.clone(),
+        None => "NWSE*1000000000".to_string(),
+    };
+    let program = match command::parse(&program) {
+        Ok(program) => program,
+        Err(why) => panic!("error: {}", why),
+    };
+
+    let start = Panel::read(path);
+    let mut panel = start.clone();
+    let animation = animate.then_some(Animation { delay, colour });
+    let (pt1, pt2, cycles) = tilt::tilt(&mut panel, &program, edge, animation.as_ref());
 
-    println!("pt1: {}", pt1);
-    println!("pt2: {}", pt2);
+    if render {
+        print!("{}", panel);
+    }
+    if diff {
+        print!("{}", start.diff(&panel, colour));
+    }
+    match edge {
+        ShiftType::Up => {
+            println!("pt1: {}", pt1);
+            println!("pt2: {}", pt2);
+        }
+        _ => {
+            println!("pt1 (tilted {0}, load on {0}): {1}", edge.letter(), pt1);
+            println!("pt2 (load on {}): {}", edge.letter(), pt2);
+        }
+    }
+    for (step, cycle) in program.iter().zip(cycles) {
+        if let Some(cycle) = cycle {
+            println!(
+                "{}: prefix: {}, period: {}",
+                step, cycle.prefix, cycle.period
+            );
+        }
+    }
 }
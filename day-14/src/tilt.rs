@@ -1,57 +1,154 @@
 use std::{
+    collections::HashMap,
+    fmt,
     fs::File,
-    io::{prelude::*, BufReader},
+    io::{self, prelude::*, BufReader},
     path::Path,
+    thread,
+    time::Duration,
 };
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum Debris {
-    Stone,
-    Cube,
-    Blank,
-}
+use crate::command::Step;
 
-enum ShiftType {
+/// A direction to tilt the panel in, or the edge the stones roll towards.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShiftType {
     Up,
     Down,
     Left,
     Right,
 }
 
-struct Panel {
-    panel: Vec<Vec<Debris>>,
-    row_rocks: Vec<Vec<usize>>,
-    col_rocks: Vec<Vec<usize>>,
+impl ShiftType {
+    /// Reads a compass letter, north being up.
+    pub fn from(c: char) -> Option<Self> {
+        match c.to_ascii_uppercase() {
+            'N' => Some(ShiftType::Up),
+            'S' => Some(ShiftType::Down),
+            'W' => Some(ShiftType::Left),
+            'E' => Some(ShiftType::Right),
+            _ => None,
+        }
+    }
+
+    pub fn letter(&self) -> char {
+        match self {
+            ShiftType::Up => 'N',
+            ShiftType::Down => 'S',
+            ShiftType::Left => 'W',
+            ShiftType::Right => 'E',
+        }
+    }
+}
+
+/// The panel as bitboards of round stones and cube rocks, each row packed into `words` `u64`s.
+#[derive(Clone)]
+pub struct Panel {
+    width: usize,
+    height: usize,
+    words: usize,
+    stones: Vec<u64>,
+    cubes: Vec<u64>,
 }
 
 impl Panel {
     fn new() -> Self {
         Self {
-            panel: vec![],
-            row_rocks: vec![],
-            col_rocks: vec![],
+            width: 0,
+            height: 0,
+            words: 0,
+            stones: vec![],
+            cubes: vec![],
         }
     }
 
+    pub fn read(path: &Path) -> Self {
+        let file = File::open(path).expect("Error opening file!");
+        let reader = BufReader::new(file);
+
+        let mut panel = Panel::new();
+
+        for l in reader.lines().map_while(Result::ok) {
+            panel.add_row(l);
+        }
+        panel
+    }
+
     fn add_row(&mut self, row: String) {
-        let mut new_row = vec![];
-        self.row_rocks.push(vec![]);
-        for (i, c) in row.chars().enumerate() {
-            if self.col_rocks.len() <= i {
-                self.col_rocks.push(vec![]);
+        let cells: Vec<char> = row.chars().collect();
+        if self.height == 0 {
+            self.width = cells.len();
+            self.words = cells.len().div_ceil(64);
+        } else if cells.len() != self.width {
+            panic!("rows must all be the same width!");
+        }
+
+        let start = self.stones.len();
+        self.stones.resize(start + self.words, 0);
+        self.cubes.resize(start + self.words, 0);
+        for (j, c) in cells.into_iter().enumerate() {
+            let bit = 1 << (j % 64);
+            match c {
+                'O' => self.stones[start + j / 64] |= bit,
+                '#' => self.cubes[start + j / 64] |= bit,
+                _ => {}
             }
-            let debris = match c {
-                '#' => Debris::Cube,
-                'O' => Debris::Stone,
-                _ => Debris::Blank,
-            };
-            if debris == Debris::Cube {
-                self.col_rocks[i].push(self.panel.len());
-                self.row_rocks[self.panel.len()].push(i);
+        }
+        self.height += 1;
+    }
+
+    /// The puzzle's character for the cell at `row`, `column`.
+    fn cell(&self, row: usize, column: usize) -> char {
+        let (w, bit) = (row * self.words + column / 64, 1 << (column % 64));
+        if self.stones[w] & bit != 0 {
+            'O'
+        } else if self.cubes[w] & bit != 0 {
+            '#'
+        } else {
+            '.'
+        }
+    }
+
+    /// Draws `after` with the stones that moved since this panel picked out.
+    pub fn diff(&self, after: &Panel, colour: bool) -> String {
+        const ARRIVED: &str = "\x1b[1;32m";
+        const LEFT: &str = "\x1b[31m";
+        const RESET: &str = "\x1b[0m";
+
+        let mut out = String::new();
+        for row in 0..after.height {
+            for column in 0..after.width {
+                let (old, new) = (self.cell(row, column), after.cell(row, column));
+                match (old == new, new, colour) {
+                    (true, _, _) => out.push(new),
+                    (false, 'O', true) => out.push_str(&format!("{}O{}", ARRIVED, RESET)),
+                    (false, 'O', false) => out.push('+'),
+                    (false, _, true) => out.push_str(&format!("{}.{}", LEFT, RESET)),
+                    (false, _, false) => out.push('-'),
+                }
             }
-            new_row.push(debris);
+            out.push('\n');
+        }
+        out
+    }
+
+    /// The stones, which are all that change as the panel is tilted.
+    fn state(&self) -> &[u64] {
+        &self.stones
+    }
+
+    fn set_state(&mut self, stones: &[u64]) {
+        self.stones.copy_from_slice(stones);
+    }
+
+    /// Bits of word `k` that fall inside the panel.
+    fn mask(&self, k: usize) -> u64 {
+        let used = self.width - 64 * k;
+        if used >= 64 {
+            !0
+        } else {
+            (1 << used) - 1
         }
-        self.panel.push(new_row);
     }
 
     fn shift(&mut self, shift_type: ShiftType) {
@@ -71,136 +168,104 @@ impl Panel {
         }
     }
 
+    /// Settles the rows in turn from the edge being tilted towards, a word of columns at a time.
     fn vertical_shift(&mut self, north: bool) {
-        let rocks = self.col_rocks.clone();
-        for (i, col_rocks) in rocks.iter().enumerate() {
-            let mut ranges = vec![];
-            if col_rocks.len() == 0 {
-                ranges.push((0, self.panel.len()));
-            } else {
-                let mut start = 0;
-                for j in col_rocks {
-                    ranges.push((start, *j));
-                    start = *j + 1;
-                }
-                ranges.push((start, self.panel.len()));
-            }
-
-            self.sort_vert_range(i, &ranges, north);
-        }
-    }
-
-    fn sort_vert_range(&mut self, column: usize, ranges: &Vec<(usize, usize)>, north: bool) {
-        for (start, stop) in ranges {
-            let mut stones = 0;
-            let mut blanks = 0;
-            for i in *start..*stop {
-                match self.panel[i][column] {
-                    Debris::Stone => {
-                        stones += 1;
+        let rows: Vec<usize> = if north {
+            (0..self.height).collect()
+        } else {
+            (0..self.height).rev().collect()
+        };
+        for (n, &row) in rows.iter().enumerate().skip(1) {
+            for k in 0..self.words {
+                let mut rolling = self.stones[row * self.words + k];
+                let mut from = row;
+                for &to in rows[..n].iter().rev() {
+                    let (a, b) = (to * self.words + k, from * self.words + k);
+                    let moving = rolling & !(self.stones[a] | self.cubes[a]);
+                    if moving == 0 {
+                        break;
                     }
-                    Debris::Blank => {
-                        blanks += 1;
-                    }
-                    _ => {}
-                }
-            }
-            let primary;
-            let secondary;
-            let mut primary_count;
-            if north {
-                primary_count = stones;
-                primary = Debris::Stone;
-                secondary = Debris::Blank;
-            } else {
-                primary_count = blanks;
-                primary = Debris::Blank;
-                secondary = Debris::Stone;
-            }
-            for i in *start..*stop {
-                if primary_count > 0 {
-                    self.panel[i][column] = primary;
-                    primary_count -= 1;
-                } else {
-                    self.panel[i][column] = secondary;
+                    self.stones[a] |= moving;
+                    self.stones[b] &= !moving;
+                    rolling = moving;
+                    from = to;
                 }
             }
         }
     }
 
+    /// Moves every stone with a gap in front of it along by one column, all
+    /// at once, until none can move.
     fn horizontal_shift(&mut self, west: bool) {
-        let rocks = self.row_rocks.clone();
-        for (i, row_rocks) in rocks.iter().enumerate() {
-            let mut ranges = vec![];
-            if row_rocks.len() == 0 {
-                ranges.push((0, self.panel[0].len()));
-            } else {
-                let mut start = 0;
-                for j in row_rocks {
-                    ranges.push((start, *j));
-                    start = *j + 1;
+        for row in 0..self.height {
+            let range = row * self.words..(row + 1) * self.words;
+            loop {
+                let free: Vec<u64> = range
+                    .clone()
+                    .enumerate()
+                    .map(|(k, w)| !(self.stones[w] | self.cubes[w]) & self.mask(k))
+                    .collect();
+                let stones = &mut self.stones[range.clone()];
+                // a stone moves if the cell it is heading into is free
+                let moving: Vec<u64> = if west {
+                    shift_up(&free)
+                } else {
+                    shift_down(&free)
                 }
-                ranges.push((start, self.panel[0].len()));
-            }
-            self.sort_horz_range(i, &ranges, west);
-        }
-    }
-
-    fn sort_horz_range(&mut self, row: usize, ranges: &Vec<(usize, usize)>, west: bool) {
-        for (start, stop) in ranges {
-            let mut stones = 0;
-            let mut blanks = 0;
-            for j in *start..*stop {
-                match self.panel[row][j] {
-                    Debris::Stone => {
-                        stones += 1;
-                    }
-                    Debris::Blank => {
-                        blanks += 1;
-                    }
-                    _ => {}
+                .iter()
+                .zip(stones.iter())
+                .map(|(f, s)| f & s)
+                .collect();
+                if moving.iter().all(|&m| m == 0) {
+                    break;
                 }
-            }
-            let primary;
-            let secondary;
-            let mut primary_count;
-            if west {
-                primary_count = stones;
-                primary = Debris::Stone;
-                secondary = Debris::Blank;
-            } else {
-                primary_count = blanks;
-                primary = Debris::Blank;
-                secondary = Debris::Stone;
-            }
-            for j in *start..*stop {
-                if primary_count > 0 {
-                    self.panel[row][j] = primary;
-                    primary_count -= 1;
+                let moved = if west {
+                    shift_down(&moving)
                 } else {
-                    self.panel[row][j] = secondary;
+                    shift_up(&moving)
+                };
+                for k in 0..stones.len() {
+                    stones[k] = (stones[k] & !moving[k]) | moved[k];
                 }
             }
         }
     }
 
-    fn get_load(&self) -> i32 {
+    /// The load on the support beams along `edge`: each stone weighs as
+    /// much as its distance from the opposite edge, counting its own cell.
+    fn get_load(&self, edge: ShiftType) -> i32 {
         let mut total = 0;
-        let total_rows = self.panel.len() as i32;
-        for (i, row) in self.panel.iter().enumerate() {
-            for d in row {
-                match d {
-                    Debris::Stone => {
-                        total += total_rows - (i as i32);
+        for row in 0..self.height {
+            let words = &self.stones[row * self.words..(row + 1) * self.words];
+            match edge {
+                ShiftType::Up | ShiftType::Down => {
+                    let stones: u32 = words.iter().map(|w| w.count_ones()).sum();
+                    let weight = match edge {
+                        ShiftType::Up => self.height - row,
+                        _ => row + 1,
+                    };
+                    total += stones as i32 * weight as i32;
+                }
+                ShiftType::Left | ShiftType::Right => {
+                    for (k, &word) in words.iter().enumerate() {
+                        let mut bits = word;
+                        while bits != 0 {
+                            let column = 64 * k + bits.trailing_zeros() as usize;
+                            bits &= bits - 1;
+                            total += match edge {
+                                ShiftType::Left => self.width - column,
+                                _ => column + 1,
+                            } as i32;
+                        }
                     }
-                    _ => {}
                 }
             }
         }
-        return total;
+        total
     }
 
-    pub fn cycle(&mut self) {
+    #[cfg(test)]
+    fn cycle(&mut self) {
         self.shift(ShiftType::Up);
         self.shift(ShiftType::Left);
         self.shift(ShiftType::Down);
@@ -208,28 +273,277 @@ impl Panel {
     }
 }
 
-pub fn tilt(path: &Path) -> (i32, i32) {
-    let file = File::open(path).expect("Error opening file!");
-    let reader = BufReader::new(file);
+impl fmt::Display for Panel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in 0..self.height {
+            for column in 0..self.width {
+                write!(f, "{}", self.cell(row, column))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Moves every bit of a multi-word row one column higher.
+fn shift_up(words: &[u64]) -> Vec<u64> {
+    let mut carry = 0;
+    words
+        .iter()
+        .map(|&w| {
+            let shifted = (w << 1) | carry;
+            carry = w >> 63;
+            shifted
+        })
+        .collect()
+}
+
+/// Moves every bit of a multi-word row one column lower.
+fn shift_down(words: &[u64]) -> Vec<u64> {
+    let mut carry = 0;
+    let mut shifted: Vec<u64> = words
+        .iter()
+        .rev()
+        .map(|&w| {
+            let shifted = (w >> 1) | carry;
+            carry = w << 63;
+            shifted
+        })
+        .collect();
+    shifted.reverse();
+    shifted
+}
+
+/// Where the sequence of panel states after each spin cycle starts to
+/// repeat: the states from `prefix` onwards recur every `period` cycles.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct CycleInfo {
+    pub prefix: usize,
+    pub period: usize,
+}
+
+/// Redraws the panel after every tilt, showing what moved.
+pub struct Animation {
+    pub delay: Duration,
+    pub colour: bool,
+}
+
+impl Animation {
+    fn frame(&self, title: &str, before: &Panel, after: &Panel) {
+        // clear the screen and draw from the top left
+        print!(
+            "\x1b[2J\x1b[H{}\n{}",
+            title,
+            before.diff(after, self.colour)
+        );
+        io::stdout().flush().expect("error writing frame!");
+        thread::sleep(self.delay);
+    }
+}
+
+/// Applies `step`, skipping ahead once a state repeats; returns the loop if one was found.
+fn spin(panel: &mut Panel, step: &Step, animation: Option<&Animation>) -> Option<CycleInfo> {
+    let mut seen: HashMap<Vec<u64>, usize> = HashMap::new();
+    let mut states: Vec<Vec<u64>> = vec![];
+    for i in 0..step.repeats {
+        if let Some(&prefix) = seen.get(panel.state()) {
+            let period = i - prefix;
+            let before = panel.clone();
+            panel.set_state(&states[prefix + (step.repeats - prefix) % period]);
+            if let Some(animation) = animation {
+                let title = format!("{}: loop of {} found, skipping to the end", step, period);
+                animation.frame(&title, &before, panel);
+            }
+            return Some(CycleInfo { prefix, period });
+        }
+        seen.insert(panel.state().to_vec(), i);
+        states.push(panel.state().to_vec());
+        for &tilt in &step.tilts {
+            match animation {
+                Some(animation) => {
+                    let before = panel.clone();
+                    panel.shift(tilt);
+                    let title = format!("{}: repeat {}, tilt {}", step, i + 1, tilt.letter());
+                    animation.frame(&title, &before, panel);
+                }
+                None => panel.shift(tilt),
+            }
+        }
+    }
+    None
+}
+
+/// The load against `edge` after one tilt towards it and after running `program`.
+pub fn tilt(
+    panel: &mut Panel,
+    program: &[Step],
+    edge: ShiftType,
+    animation: Option<&Animation>,
+) -> (i32, i32, Vec<Option<CycleInfo>>) {
+    let mut tilted = panel.clone();
+    tilted.shift(edge);
+    let pt1 = tilted.get_load(edge);
 
+    let cycles = program
+        .iter()
+        .map(|step| spin(panel, step, animation))
+        .collect();
+    let pt2 = panel.get_load(edge);
+
+    (pt1, pt2, cycles)
+}
+
+#[cfg(test)]
+fn test_panel() -> Panel {
     let mut panel = Panel::new();
+    for row in [
+        "O....#....",
+        "O.OO#....#",
+        ".....##...",
+        "OO.#O....O",
+        ".O.....O#.",
+        "O.#..O.#.#",
+        "..O..#O..O",
+        ".......O..",
+        "#....###..",
+        "#OO..#....",
+    ] {
+        panel.add_row(row.to_string());
+    }
+    panel
+}
 
-    for l in reader.lines().flatten() {
-        panel.add_row(l);
+#[test]
+fn spin_cycle_detection() {
+    let spin_cycles = |repeats| Step {
+        tilts: vec![
+            ShiftType::Up,
+            ShiftType::Left,
+            ShiftType::Down,
+            ShiftType::Right,
+        ],
+        repeats,
+    };
+    let mut panel = test_panel();
+    let cycle = spin(&mut panel, &spin_cycles(1_000_000_000), None);
+    assert_eq!(panel.get_load(ShiftType::Up), 64);
+    assert_eq!(
+        cycle,
+        Some(CycleInfo {
+            prefix: 3,
+            period: 7
+        })
+    );
+
+    // short runs finish before the loop is found and match a plain simulation
+    for cycles in [0, 1, 2, 3] {
+        let mut expected = test_panel();
+        for _ in 0..cycles {
+            expected.cycle();
+        }
+        let mut panel = test_panel();
+        assert_eq!(spin(&mut panel, &spin_cycles(cycles), None), None);
+        assert_eq!(panel.state(), expected.state());
     }
 
-    panel.shift(ShiftType::Up);
-    let pt1 = panel.get_load();
+    // and past the start of the loop, jumping agrees with simulating
+    for cycles in [10, 11, 25] {
+        let mut expected = test_panel();
+        for _ in 0..cycles {
+            expected.cycle();
+        }
+        let mut panel = test_panel();
+        spin(&mut panel, &spin_cycles(cycles), None);
+        assert_eq!(panel.state(), expected.state());
+    }
+}
 
-    panel.shift(ShiftType::Left);
-    panel.shift(ShiftType::Down);
-    panel.shift(ShiftType::Right);
+#[test]
+fn load_on_each_edge() {
+    let mut panel = Panel::new();
+    for row in ["O..", ".#O", "..."] {
+        panel.add_row(row.to_string());
+    }
+    assert_eq!(panel.get_load(ShiftType::Up), 3 + 2);
+    assert_eq!(panel.get_load(ShiftType::Down), 1 + 2);
+    assert_eq!(panel.get_load(ShiftType::Left), 3 + 1);
+    assert_eq!(panel.get_load(ShiftType::Right), 1 + 3);
+
+    // the first part tilts towards the edge it weighs
+    assert_eq!(tilt(&mut panel.clone(), &[], ShiftType::Up, None).0, 3 + 3);
+    assert_eq!(
+        tilt(&mut panel.clone(), &[], ShiftType::Down, None).0,
+        3 + 3
+    );
+}
+
+#[test]
+fn bitboard_tilts_match_cell_by_cell() {
+    // wider than two words, so stones have to roll across word boundaries
+    let (width, height) = (150, 12);
+    let mut grid: Vec<Vec<char>> = (0..height)
+        .map(|i: usize| {
+            (0..width)
+                .map(|j: usize| match (i * 7919 + j * 104_729) % 11 {
+                    0 | 1 => '#',
+                    2..=5 => 'O',
+                    _ => '.',
+                })
+                .collect()
+        })
+        .collect();
+    let mut panel = Panel::new();
+    for row in &grid {
+        panel.add_row(row.iter().collect());
+    }
 
-    for _ in 0..999 {
-        panel.cycle();
+    // roll each stone one cell at a time until nothing moves
+    let (di, dj) = ([-1, 0, 1, 0], [0, -1, 0, 1]);
+    for step in 0..8 {
+        let d = step % 4;
+        let mut moved = true;
+        while moved {
+            moved = false;
+            for i in 0..height {
+                for j in 0..width {
+                    let (ti, tj) = (i as i32 + di[d], j as i32 + dj[d]);
+                    if grid[i][j] != 'O' || ti < 0 || tj < 0 {
+                        continue;
+                    }
+                    let (ti, tj) = (ti as usize, tj as usize);
+                    if ti < height && tj < width && grid[ti][tj] == '.' {
+                        grid[ti][tj] = 'O';
+                        grid[i][j] = '.';
+                        moved = true;
+                    }
+                }
+            }
+        }
+        panel.shift(
+            [
+                ShiftType::Up,
+                ShiftType::Left,
+                ShiftType::Down,
+                ShiftType::Right,
+            ][d],
+        );
+        let expected: String = grid
+            .iter()
+            .map(|row| row.iter().collect::<String>() + "\n")
+            .collect();
+        assert_eq!(panel.to_string(), expected);
     }
+}
 
-    let pt2 = panel.get_load();
+#[test]
+fn render_and_diff() {
+    let before = test_panel();
+    assert_eq!(before.to_string().lines().nth(1), Some("O.OO#....#"));
 
-    return (pt1, pt2);
+    let mut after = before.clone();
+    after.shift(ShiftType::Up);
+    let diff = before.diff(&after, false);
+    let rows: Vec<&str> = diff.lines().collect();
+    assert_eq!(&rows[..3], ["O+++.#.+..", "O+--#....#", "++..+##..+"]);
+    assert_eq!(before.diff(&before, false), before.to_string());
 }
@@ -0,0 +1,252 @@
+use crate::module::Kind;
+
+/// The wiring of a network, with modules numbered in name order. Outputs
+/// nothing is defined for, like `rx`, are nodes with no kind.
+pub struct Graph {
+    names: Vec<String>,
+    kinds: Vec<Option<Kind>>,
+    outputs: Vec<Vec<usize>>,
+}
+
+/// A chain of flip-flops counting presses, reset by a conjunction every `period` presses.
+#[derive(Debug, PartialEq)]
+pub struct BinaryCounter {
+    pub conjunction: String,
+    /// Least significant bit first.
+    pub bits: Vec<String>,
+    pub period: u64,
+}
+
+impl Graph {
+    pub fn new(modules: Vec<(String, Kind, Vec<String>)>) -> Self {
+        let mut names: Vec<String> = modules.iter().map(|m| m.0.clone()).collect();
+        for (_, _, outputs) in &modules {
+            names.extend(outputs.iter().cloned());
+        }
+        names.sort();
+        names.dedup();
+
+        let index = |name: &str| names.binary_search_by(|n| n.as_str().cmp(name)).unwrap();
+        let mut kinds = vec![None; names.len()];
+        let mut outputs = vec![vec![]; names.len()];
+        for (name, kind, targets) in &modules {
+            kinds[index(name)] = Some(*kind);
+            outputs[index(name)] = targets.iter().map(|t| index(t)).collect();
+        }
+        Self {
+            names,
+            kinds,
+            outputs,
+        }
+    }
+
+    fn find(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|n| n == name)
+    }
+
+    /// Groups of modules that loop through each other, found with Tarjan's algorithm.
+    pub fn strongly_connected(&self) -> Vec<Vec<&str>> {
+        const UNSEEN: usize = usize::MAX;
+        let n = self.names.len();
+        let mut index = vec![UNSEEN; n];
+        let mut low = vec![0; n];
+        let mut on_stack = vec![false; n];
+        let mut stack = vec![];
+        let mut components = vec![];
+        let mut next = 0;
+
+        for root in 0..n {
+            if index[root] != UNSEEN {
+                continue;
+            }
+            // each frame is a node and how many of its outputs are done
+            let mut frames = vec![(root, 0)];
+            index[root] = next;
+            low[root] = next;
+            next += 1;
+            stack.push(root);
+            on_stack[root] = true;
+
+            while let Some(&(v, edge)) = frames.last() {
+                if let Some(&w) = self.outputs[v].get(edge) {
+                    frames.last_mut().expect("frame just read").1 += 1;
+                    if index[w] == UNSEEN {
+                        index[w] = next;
+                        low[w] = next;
+                        next += 1;
+                        stack.push(w);
+                        on_stack[w] = true;
+                        frames.push((w, 0));
+                    } else if on_stack[w] {
+                        low[v] = low[v].min(index[w]);
+                    }
+                    continue;
+                }
+
+                frames.pop();
+                if let Some(&(parent, _)) = frames.last() {
+                    low[parent] = low[parent].min(low[v]);
+                }
+                if low[v] == index[v] {
+                    let mut component = vec![];
+                    while let Some(w) = stack.pop() {
+                        on_stack[w] = false;
+                        component.push(w);
+                        if w == v {
+                            break;
+                        }
+                    }
+                    if component.len() > 1 || self.outputs[v].contains(&v) {
+                        component.sort();
+                        components.push(component);
+                    }
+                }
+            }
+        }
+        components
+            .iter()
+            .map(|c| c.iter().map(|&k| self.names[k].as_str()).collect())
+            .collect()
+    }
+
+    /// The binary counters hanging off the broadcaster.
+    pub fn counters(&self) -> Vec<BinaryCounter> {
+        let broadcaster = match self.find("broadcaster") {
+            Some(broadcaster) => broadcaster,
+            None => return vec![],
+        };
+        self.outputs[broadcaster]
+            .iter()
+            .filter_map(|&start| self.counter_from(start))
+            .collect()
+    }
+
+    fn counter_from(&self, start: usize) -> Option<BinaryCounter> {
+        let of_kind = |v: usize, kind| -> Vec<usize> {
+            self.outputs[v]
+                .iter()
+                .copied()
+                .filter(|&w| self.kinds[w] == Some(kind))
+                .collect()
+        };
+
+        let mut chain = vec![];
+        let mut conjunction = None;
+        let mut period = 0;
+        let mut current = Some(start);
+        while let Some(v) = current {
+            if self.kinds[v] != Some(Kind::FlipFlop) || chain.contains(&v) || chain.len() >= 64 {
+                return None;
+            }
+            let flip_flops = of_kind(v, Kind::FlipFlop);
+            let conjunctions = of_kind(v, Kind::Conjunction);
+            if flip_flops.len() > 1 || conjunctions.len() > 1 {
+                return None;
+            }
+            if let Some(&c) = conjunctions.first() {
+                if conjunction.is_some_and(|seen| seen != c) {
+                    return None;
+                }
+                conjunction = Some(c);
+                period |= 1 << chain.len();
+            }
+            chain.push(v);
+            current = flip_flops.first().copied();
+        }
+
+        let conjunction = conjunction?;
+        if of_kind(conjunction, Kind::FlipFlop)
+            .iter()
+            .any(|w| !chain.contains(w))
+        {
+            return None;
+        }
+        Some(BinaryCounter {
+            conjunction: self.names[conjunction].clone(),
+            bits: chain.iter().map(|&v| self.names[v].clone()).collect(),
+            period,
+        })
+    }
+
+    /// Modules no pulse from the broadcaster can ever reach.
+    pub fn unreachable(&self) -> Vec<&str> {
+        let mut seen = vec![false; self.names.len()];
+        let mut stack: Vec<usize> = self.find("broadcaster").into_iter().collect();
+        while let Some(v) = stack.pop() {
+            if !seen[v] {
+                seen[v] = true;
+                stack.extend(&self.outputs[v]);
+            }
+        }
+        (0..self.names.len())
+            .filter(|&v| !seen[v] && self.kinds[v].is_some())
+            .map(|v| self.names[v].as_str())
+            .collect()
+    }
+
+    /// Outputs that no module is defined for.
+    pub fn undefined(&self) -> Vec<&str> {
+        (0..self.names.len())
+            .filter(|&v| self.kinds[v].is_none())
+            .map(|v| self.names[v].as_str())
+            .collect()
+    }
+
+    /// The network in Graphviz's DOT language, with a shape and colour for
+    /// each kind of module.
+    pub fn dot(&self) -> String {
+        let mut out = String::from("digraph modules {\n");
+        for (v, name) in self.names.iter().enumerate() {
+            let (shape, colour) = match self.kinds[v] {
+                Some(Kind::Broadcaster) => ("doublecircle", "gold"),
+                Some(Kind::FlipFlop) => ("box", "lightblue"),
+                Some(Kind::Conjunction) => ("invtrapezium", "salmon"),
+                Some(Kind::Inverter) => ("triangle", "plum"),
+                Some(Kind::Xor) => ("diamond", "palegreen"),
+                Some(Kind::Delay) => ("cds", "wheat"),
+                Some(Kind::Latch) => ("component", "lightgrey"),
+                Some(Kind::Counter) => ("box3d", "lightcyan"),
+                None => ("plaintext", "white"),
+            };
+            out.push_str(&format!(
+                "    \"{}\" [shape={}, style=filled, fillcolor={}];\n",
+                name, shape, colour
+            ));
+        }
+        for (v, outputs) in self.outputs.iter().enumerate() {
+            for &w in outputs {
+                out.push_str(&format!(
+                    "    \"{}\" -> \"{}\";\n",
+                    self.names[v], self.names[w]
+                ));
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+#[test]
+fn example_graph() {
+    let graph = Graph::new(vec![
+        ("broadcaster".into(), Kind::Broadcaster, vec!["a".into()]),
+        ("a".into(), Kind::FlipFlop, vec!["inv".into(), "con".into()]),
+        ("inv".into(), Kind::Conjunction, vec!["b".into()]),
+        ("b".into(), Kind::FlipFlop, vec!["con".into()]),
+        ("con".into(), Kind::Conjunction, vec!["output".into()]),
+        (
+            "lost".into(),
+            Kind::FlipFlop,
+            vec!["lost".into(), "a".into()],
+        ),
+    ]);
+    assert_eq!(graph.strongly_connected(), [["lost"]]);
+    assert_eq!(graph.unreachable(), ["lost"]);
+    assert_eq!(graph.undefined(), ["output"]);
+    assert!(graph.counters().is_empty());
+
+    let dot = graph.dot();
+    assert!(dot.starts_with("digraph modules {\n"));
+    assert!(dot.contains("\"output\" [shape=plaintext"));
+    assert!(dot.contains("    \"inv\" -> \"b\";\n"));
+}
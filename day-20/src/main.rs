@@ -1,11 +1,81 @@
-use std::{env, path::Path};
+use std::{
+    env,
+    fs::{self, File},
+    io,
+    path::Path,
+};
 
-use pulse::pulse;
+use graph::Graph;
+use module::Pulse;
+use pulse::{pulse, FirstPress, SwitchArray};
+use trace::{Trace, TraceFilter};
 
+mod graph;
+mod module;
 mod pulse;
+mod trace;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let (flags, args): (Vec<String>, Vec<String>) =
+        env::args().partition(|arg| arg.starts_with("--"));
+    let mut trace = false;
+    let mut trace_file = None;
+    let mut filter = TraceFilter::default();
+    let mut cycle = None;
+    let mut extrapolate = None;
+    let mut presses = 1000;
+    let mut budget = 100_000;
+    let mut queries = vec![];
+    let mut analyse = false;
+    let mut dot = None;
+    for flag in &flags {
+        match flag.split_once('=') {
+            None if flag == "--trace" => trace = true,
+            Some(("--trace", file)) => {
+                trace = true;
+                trace_file = Some(file.to_string());
+            }
+            Some(("--node", node)) => {
+                trace = true;
+                filter
+                    .nodes
+                    .get_or_insert_with(Vec::new)
+                    .push(node.to_string());
+            }
+            Some(("--level", level)) => {
+                trace = true;
+                filter.level = Some(parse_level(level));
+            }
+            Some(("--trace-presses", range)) => {
+                trace = true;
+                let (first, last) = range.split_once('-').unwrap_or((range, range));
+                let press = |p: &str| p.parse().expect("press range must be numbers!");
+                filter.presses = Some((press(first), press(last)));
+            }
+            Some(("--count", count)) => {
+                presses = count.parse().expect("press count must be a number!");
+            }
+            Some(("--budget", count)) => {
+                budget = count.parse().expect("search budget must be a number!");
+            }
+            Some(("--query", query)) => {
+                let (node, level) = query.split_once(':').unwrap_or((query, "low"));
+                queries.push((node.to_string(), parse_level(level)));
+            }
+            None if flag == "--analyse" => analyse = true,
+            None if flag == "--dot" => dot = Some(None),
+            Some(("--dot", file)) => dot = Some(Some(file.to_string())),
+            None if flag == "--cycle" => cycle = Some(vec![]),
+            Some(("--cycle", names)) => {
+                cycle = Some(names.split(',').map(|name| name.to_string()).collect());
+            }
+            Some(("--extrapolate", presses)) => {
+                cycle.get_or_insert_with(Vec::new);
+                extrapolate = Some(presses.parse().expect("press count must be a number!"));
+            }
+            _ => panic!("unknown flag '{}'", flag),
+        }
+    }
 
     match args.len() {
         1 => {
@@ -20,8 +90,131 @@ fn main() {
     let filepath = &args[1];
     let path = Path::new(filepath);
 
-    let (pt1, pt2) = pulse(&path);
+    if analyse {
+        print_analysis(&read(path).graph());
+    }
+    match dot {
+        Some(Some(file)) => {
+            let dot = read(path).graph().dot();
+            fs::write(file, dot).expect("error writing dot file!");
+        }
+        Some(None) => print!("{}", read(path).graph().dot()),
+        None => {}
+    }
+    if let Some(names) = cycle {
+        print_cycle(&mut read(path), &names, extrapolate, budget);
+    }
+
+    let mut switch_array = read(path);
+    if trace {
+        match trace_file {
+            Some(file) => {
+                let file = File::create(file).expect("error creating trace file!");
+                switch_array.observe(Box::new(Trace::new(filter, io::BufWriter::new(file))));
+            }
+            None => switch_array.observe(Box::new(Trace::new(filter, io::stdout()))),
+        }
+    }
+
+    // part two asks when rx first gets a low pulse
+    let part_two = queries.is_empty();
+    if part_two {
+        queries.push(("rx".to_string(), Pulse::Low));
+    }
+    let (pt1, firsts) = pulse(&mut switch_array, presses, &queries, budget);
+
+    println!("pt1: {}", pt1);
+    for ((node, level), first) in queries.iter().zip(firsts) {
+        let label = if part_two {
+            "pt2".to_string()
+        } else {
+            format!("first {} pulse to {}", level, node)
+        };
+        match first {
+            Ok(Some(FirstPress::Seen(press))) => println!("{}: {}", label, press),
+            Ok(Some(FirstPress::Predicted(press))) => println!(
+                "{}: {} (predicted, past the {} press budget)",
+                label, press, budget
+            ),
+            Ok(None) => println!("{}: not found within {} presses", label, budget),
+            Err(why) => println!("{}: {}", label, why),
+        }
+    }
+}
+
+fn parse_level(level: &str) -> Pulse {
+    match level {
+        "high" => Pulse::High,
+        "low" => Pulse::Low,
+        _ => panic!("level must be 'high' or 'low'!"),
+    }
+}
+
+fn read(path: &Path) -> SwitchArray {
+    match SwitchArray::read(path) {
+        Ok(switch_array) => switch_array,
+        Err(why) => panic!("error: {}", why),
+    }
+}
+
+fn print_analysis(graph: &Graph) {
+    for component in graph.strongly_connected() {
+        println!("loop: {}", component.join(", "));
+    }
+    for counter in graph.counters() {
+        println!(
+            "counter: {} bits into {}, period {}",
+            counter.bits.len(),
+            counter.conjunction,
+            counter.period
+        );
+    }
+    for name in graph.unreachable() {
+        println!("unreachable: {}", name);
+    }
+    for name in graph.undefined() {
+        println!("undefined output: {}", name);
+    }
+}
 
-    println!("pt1: {:?}", pt1.unwrap());
-    println!("pt2: {:?}", pt2.unwrap());
+/// Looks for a loop in the states of the named modules and everything
+/// upstream of them, or the whole network if none are named.
+fn print_cycle(
+    switch_array: &mut SwitchArray,
+    names: &[String],
+    extrapolate: Option<u64>,
+    budget: u64,
+) {
+    let modules = if names.is_empty() {
+        switch_array.modules()
+    } else {
+        switch_array.upstream(names)
+    };
+    let bits = switch_array.snapshot(&modules).len();
+    println!(
+        "watching {} modules, {} bits of memory",
+        modules.len(),
+        bits
+    );
+    let cycle = match switch_array.find_cycle(&modules, budget) {
+        Some(cycle) => cycle,
+        None => {
+            println!("no cycle within {} presses", budget);
+            return;
+        }
+    };
+    println!("prefix: {}, period: {}", cycle.prefix, cycle.period);
+    if let Some(presses) = extrapolate {
+        if !names.is_empty() {
+            panic!("pulse counts can only be extrapolated for the whole network!");
+        }
+        let (low, high) = cycle.pulses(presses);
+        println!(
+            "after {} presses: {} low, {} high, product {}",
+            presses,
+            low,
+            high,
+            low as u128 * high as u128
+        );
+    }
 }
@@ -0,0 +1,403 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Pulse {
+    High,
+    #[default]
+    Low,
+}
+
+impl fmt::Display for Pulse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Pulse::High => write!(f, "high"),
+            Pulse::Low => write!(f, "low"),
+        }
+    }
+}
+
+/// Every bit of memory in some part of the network, packed into words.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct Snapshot {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl Snapshot {
+    pub fn push(&mut self, bit: bool) {
+        if self.len.is_multiple_of(64) {
+            self.words.push(0);
+        }
+        if bit {
+            self.words[self.len / 64] |= 1 << (self.len % 64);
+        }
+        self.len += 1;
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+}
+
+/// The sorts of module a network can be built from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Broadcaster,
+    FlipFlop,
+    Conjunction,
+    Inverter,
+    Xor,
+    Delay,
+    Latch,
+    Counter,
+}
+
+/// A node in the network. Every pulse it sends goes to all of its outputs.
+pub trait Module: fmt::Debug {
+    /// What sort of module this is, for reports.
+    fn kind(&self) -> Kind;
+
+    /// Tells the module it has another input. Called once per input, before
+    /// any pulses are sent, and inputs are numbered from 0 in this order.
+    fn connect(&mut self) {}
+
+    /// Handles a pulse arriving on `input`, returning what to send on, if
+    /// anything.
+    fn receive(&mut self, input: usize, pulse: Pulse) -> Option<Pulse>;
+
+    /// Appends the module's memory to `snapshot`, always in the same order.
+    fn save(&self, _snapshot: &mut Snapshot) {}
+}
+
+/// Builds the module named by `token`, a counter's divisor going before its name as in `#3name`.
+pub fn build(token: &str) -> Option<(String, Box<dyn Module>)> {
+    let mut chars = token.chars();
+    let prefix = chars.next()?;
+    let rest = chars.as_str();
+    let module: Box<dyn Module> = match prefix {
+        '%' => Box::new(FlipFlop::default()),
+        '&' => Box::new(Conjunction::default()),
+        '!' => Box::new(Inverter),
+        '^' => Box::new(Xor::default()),
+        '~' => Box::new(Delay::default()),
+        '@' => Box::new(Latch::default()),
+        '#' => {
+            let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            let divisor = rest[..digits].parse().ok().filter(|&n| n > 0)?;
+            return Some((rest[digits..].to_string(), Box::new(Counter::new(divisor))));
+        }
+        _ => return None,
+    };
+    Some((rest.to_string(), module))
+}
+
+/// Passes every pulse straight on.
+#[derive(Debug)]
+pub struct Broadcaster;
+
+impl Module for Broadcaster {
+    fn kind(&self) -> Kind {
+        Kind::Broadcaster
+    }
+
+    fn receive(&mut self, _input: usize, pulse: Pulse) -> Option<Pulse> {
+        Some(pulse)
+    }
+}
+
+/// Ignores high pulses and toggles on low ones, sending its new state.
+#[derive(Debug, Default)]
+pub struct FlipFlop {
+    state: Pulse,
+}
+
+impl Module for FlipFlop {
+    fn kind(&self) -> Kind {
+        Kind::FlipFlop
+    }
+
+    fn receive(&mut self, _input: usize, pulse: Pulse) -> Option<Pulse> {
+        match (self.state, pulse) {
+            (Pulse::Low, Pulse::Low) => {
+                self.state = Pulse::High;
+                Some(Pulse::High)
+            }
+            (Pulse::High, Pulse::Low) => {
+                self.state = Pulse::Low;
+                Some(Pulse::Low)
+            }
+            (_, Pulse::High) => None,
+        }
+    }
+
+    fn save(&self, snapshot: &mut Snapshot) {
+        snapshot.push(self.state == Pulse::High);
+    }
+}
+
+/// The last pulse from each input of a module, with a running count of the
+/// high ones so it never has to look through them all.
+#[derive(Debug, Default)]
+struct Memory {
+    inputs: Vec<Pulse>,
+    highs: usize,
+}
+
+impl Memory {
+    fn connect(&mut self) {
+        self.inputs.push(Pulse::Low);
+    }
+
+    fn set(&mut self, input: usize, pulse: Pulse) {
+        match (std::mem::replace(&mut self.inputs[input], pulse), pulse) {
+            (Pulse::Low, Pulse::High) => self.highs += 1,
+            (Pulse::High, Pulse::Low) => self.highs -= 1,
+            _ => {}
+        }
+    }
+
+    fn save(&self, snapshot: &mut Snapshot) {
+        for &pulse in &self.inputs {
+            snapshot.push(pulse == Pulse::High);
+        }
+    }
+}
+
+/// Remembers the last pulse from each input and sends low only when all of
+/// them are high.
+#[derive(Debug, Default)]
+pub struct Conjunction {
+    memory: Memory,
+}
+
+impl Module for Conjunction {
+    fn kind(&self) -> Kind {
+        Kind::Conjunction
+    }
+
+    fn connect(&mut self) {
+        self.memory.connect();
+    }
+
+    fn receive(&mut self, input: usize, pulse: Pulse) -> Option<Pulse> {
+        self.memory.set(input, pulse);
+        if self.memory.highs == self.memory.inputs.len() {
+            return Some(Pulse::Low);
+        }
+        Some(Pulse::High)
+    }
+
+    fn save(&self, snapshot: &mut Snapshot) {
+        self.memory.save(snapshot);
+    }
+}
+
+/// Sends the opposite of every pulse.
+#[derive(Debug)]
+pub struct Inverter;
+
+impl Module for Inverter {
+    fn kind(&self) -> Kind {
+        Kind::Inverter
+    }
+
+    fn receive(&mut self, _input: usize, pulse: Pulse) -> Option<Pulse> {
+        match pulse {
+            Pulse::High => Some(Pulse::Low),
+            Pulse::Low => Some(Pulse::High),
+        }
+    }
+}
+
+/// Remembers the last pulse from each input, like a conjunction, and sends
+/// high when an odd number of them are high.
+#[derive(Debug, Default)]
+pub struct Xor {
+    memory: Memory,
+}
+
+impl Module for Xor {
+    fn kind(&self) -> Kind {
+        Kind::Xor
+    }
+
+    fn connect(&mut self) {
+        self.memory.connect();
+    }
+
+    fn receive(&mut self, input: usize, pulse: Pulse) -> Option<Pulse> {
+        self.memory.set(input, pulse);
+        if self.memory.highs % 2 == 1 {
+            return Some(Pulse::High);
+        }
+        Some(Pulse::Low)
+    }
+
+    fn save(&self, snapshot: &mut Snapshot) {
+        self.memory.save(snapshot);
+    }
+}
+
+/// Sends the pulse it received before this one, starting from low.
+#[derive(Debug, Default)]
+pub struct Delay {
+    held: Pulse,
+}
+
+impl Module for Delay {
+    fn kind(&self) -> Kind {
+        Kind::Delay
+    }
+
+    fn receive(&mut self, _input: usize, pulse: Pulse) -> Option<Pulse> {
+        Some(std::mem::replace(&mut self.held, pulse))
+    }
+
+    fn save(&self, snapshot: &mut Snapshot) {
+        snapshot.push(self.held == Pulse::High);
+    }
+}
+
+/// Holds the level of the last pulse it received, starting from low, and
+/// only sends when that level changes.
+#[derive(Debug, Default)]
+pub struct Latch {
+    state: Pulse,
+}
+
+impl Module for Latch {
+    fn kind(&self) -> Kind {
+        Kind::Latch
+    }
+
+    fn receive(&mut self, _input: usize, pulse: Pulse) -> Option<Pulse> {
+        if pulse == self.state {
+            return None;
+        }
+        self.state = pulse;
+        Some(pulse)
+    }
+
+    fn save(&self, snapshot: &mut Snapshot) {
+        snapshot.push(self.state == Pulse::High);
+    }
+}
+
+/// Counts low pulses, sending high on every `divisor`th and low otherwise.
+#[derive(Debug)]
+pub struct Counter {
+    divisor: u32,
+    count: u32,
+}
+
+impl Counter {
+    pub fn new(divisor: u32) -> Self {
+        Self { divisor, count: 0 }
+    }
+}
+
+impl Module for Counter {
+    fn kind(&self) -> Kind {
+        Kind::Counter
+    }
+
+    fn receive(&mut self, _input: usize, pulse: Pulse) -> Option<Pulse> {
+        if pulse == Pulse::High {
+            return None;
+        }
+        self.count = (self.count + 1) % self.divisor;
+        if self.count == 0 {
+            return Some(Pulse::High);
+        }
+        Some(Pulse::Low)
+    }
+
+    fn save(&self, snapshot: &mut Snapshot) {
+        let width = 32 - (self.divisor - 1).leading_zeros();
+        for bit in 0..width {
+            snapshot.push(self.count & (1 << bit) != 0);
+        }
+    }
+}
+
+#[cfg(test)]
+fn feed(module: &mut dyn Module, pulses: &[(usize, Pulse)]) -> Vec<Option<Pulse>> {
+    pulses
+        .iter()
+        .map(|&(input, pulse)| module.receive(input, pulse))
+        .collect()
+}
+
+#[test]
+fn snapshots() {
+    let mut snapshot = Snapshot::default();
+    for k in 0..70 {
+        snapshot.push(k % 3 == 0);
+    }
+    assert_eq!(snapshot.len(), 70);
+    assert_eq!(snapshot.words, [0x9249249249249249, 0x24]);
+
+    let (_, mut counter) = build("#6c").unwrap();
+    for _ in 0..5 {
+        counter.receive(0, Pulse::Low);
+    }
+    let mut snapshot = Snapshot::default();
+    counter.save(&mut snapshot);
+    assert_eq!((snapshot.len(), snapshot.words[0]), (3, 5));
+}
+
+#[test]
+fn puzzle_modules() {
+    use Pulse::*;
+    let (_, mut flop) = build("%a").unwrap();
+    assert_eq!(
+        feed(flop.as_mut(), &[(0, High), (0, Low), (0, Low)]),
+        [None, Some(High), Some(Low)]
+    );
+
+    let (name, mut conj) = build("&inv").unwrap();
+    assert_eq!(name, "inv");
+    conj.connect();
+    conj.connect();
+    assert_eq!(
+        feed(conj.as_mut(), &[(0, High), (1, High), (0, Low)]),
+        [Some(High), Some(Low), Some(High)]
+    );
+    assert!(build("?a").is_none());
+}
+
+#[test]
+fn extra_modules() {
+    use Pulse::*;
+    let (_, mut inverter) = build("!n").unwrap();
+    assert_eq!(feed(inverter.as_mut(), &[(0, Low)]), [Some(High)]);
+
+    let (_, mut xor) = build("^x").unwrap();
+    xor.connect();
+    xor.connect();
+    assert_eq!(
+        feed(xor.as_mut(), &[(0, High), (1, High), (0, Low)]),
+        [Some(High), Some(Low), Some(High)]
+    );
+
+    let (_, mut delay) = build("~d").unwrap();
+    assert_eq!(
+        feed(delay.as_mut(), &[(0, High), (0, High), (0, Low)]),
+        [Some(Low), Some(High), Some(High)]
+    );
+
+    let (_, mut latch) = build("@l").unwrap();
+    assert_eq!(
+        feed(latch.as_mut(), &[(0, Low), (0, High), (0, High), (0, Low)]),
+        [None, Some(High), None, Some(Low)]
+    );
+
+    let (name, mut counter) = build("#3c").unwrap();
+    assert_eq!(name, "c");
+    assert_eq!(
+        feed(counter.as_mut(), &[(0, Low), (0, High), (0, Low), (0, Low)]),
+        [Some(Low), None, Some(Low), Some(High)]
+    );
+    assert!(build("#0c").is_none());
+    assert!(build("#c").is_none());
+}
@@ -1,114 +1,203 @@
 use std::{
     collections::{HashMap, VecDeque},
+    fmt,
     fs::File,
     io::{prelude::*, BufReader},
     path::Path,
 };
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum Pulse {
-    High,
-    Low,
-}
+use crate::{
+    graph::Graph,
+    module::{self, Broadcaster, Kind, Module, Pulse, Snapshot},
+    trace::{Event, Observer},
+};
 
-#[derive(Clone, Debug)]
-struct FlipFlop {
-    state: Pulse,
+#[derive(Debug)]
+struct Switch {
+    module: Box<dyn Module>,
+    outputs: Vec<String>,
 }
 
-impl FlipFlop {
-    fn new() -> Self {
-        Self { state: Pulse::Low }
-    }
+/// The states after `prefix` presses recur every `period`, with the pulses sent on each press.
+#[derive(Debug, PartialEq)]
+pub struct NetworkCycle {
+    pub prefix: u64,
+    pub period: u64,
+    pulses: Vec<(u64, u64)>,
+}
 
-    fn pulse(&mut self, pulse: Pulse) -> Option<Pulse> {
-        match (self.state, pulse) {
-            (Pulse::Low, Pulse::Low) => {
-                self.state = Pulse::High;
-                Some(Pulse::High)
-            }
-            (Pulse::High, Pulse::Low) => {
-                self.state = Pulse::Low;
-                Some(Pulse::Low)
-            }
-            (_, Pulse::High) => None,
+impl NetworkCycle {
+    /// The low and high pulses sent over the first `presses` presses. Only
+    /// meaningful when the cycle covers the whole network.
+    pub fn pulses(&self, presses: u64) -> (u64, u64) {
+        let sum = |range: std::ops::Range<u64>| {
+            self.pulses[range.start as usize..range.end as usize]
+                .iter()
+                .fold((0, 0), |(low, high), p| (low + p.0, high + p.1))
+        };
+        if presses <= self.prefix {
+            return sum(0..presses);
         }
+        let (loops, rest) = (
+            (presses - self.prefix) / self.period,
+            (presses - self.prefix) % self.period,
+        );
+        let head = sum(0..self.prefix + rest);
+        let body = sum(self.prefix..self.prefix + self.period);
+        (head.0 + loops * body.0, head.1 + loops * body.1)
     }
 }
 
-#[derive(Clone, Debug)]
-struct Conjunction {
-    inputs: HashMap<String, Pulse>,
-    first_high: HashMap<String, u64>,
-}
+/// How many evenly spaced high pulses each input of the conjunction feeding
+/// a sink has to send before its period is trusted.
+const CYCLES: usize = 3;
 
-impl Conjunction {
-    fn new() -> Self {
-        Self {
-            inputs: HashMap::new(),
-            first_high: HashMap::new(),
-        }
-    }
+#[derive(Debug, PartialEq)]
+pub enum SinkError {
+    NoInputs(String),
+    Irregular(String, Vec<u64>),
+    NeverAligned(Vec<(u64, u64)>),
+    Overflow(Vec<(u64, u64)>),
+}
 
-    fn pulse(&mut self, button_presses: u64, source: String, pulse: Pulse) -> Pulse {
-        self.inputs.insert(source.clone(), pulse);
-        if pulse == Pulse::High && self.first_high.get(&source) == None {
-            self.first_high.insert(source, button_presses);
-        }
-        if self.inputs.clone().iter().all(|x| *x.1 == Pulse::High) {
-            return Pulse::Low;
+impl fmt::Display for SinkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SinkError::NoInputs(sink) => write!(f, "nothing sends pulses to '{}'", sink),
+            SinkError::Irregular(input, presses) => {
+                write!(
+                    f,
+                    "'{}' is not periodic, it went high on presses {:?}",
+                    input, presses
+                )
+            }
+            SinkError::NeverAligned(schedules) => {
+                write!(
+                    f,
+                    "inputs going high on (start, period) {:?} never line up",
+                    schedules
+                )
+            }
+            SinkError::Overflow(schedules) => {
+                write!(
+                    f,
+                    "inputs going high on (start, period) {:?} line up too late to count",
+                    schedules
+                )
+            }
         }
-        Pulse::High
     }
 }
 
-#[derive(Clone, Debug)]
-enum SwitchType {
-    FlipFlop(FlipFlop),
-    Conjunction(Conjunction),
-    Broadcaster,
+/// When a watched sink first receives its pulse.
+#[derive(Debug, PartialEq)]
+pub enum FirstPress {
+    /// Seen while pressing the button.
+    Seen(u64),
+    /// Worked out from the rhythm of the inputs feeding it, but too far past
+    /// the budget to check.
+    Predicted(u64),
 }
 
-#[derive(Clone, Debug)]
-struct Switch {
-    switch_type: SwitchType,
-    outputs: Vec<String>,
+/// Watches a sink for its first pulse at `level`.
+struct SinkWatch {
+    sink: usize,
+    level: Pulse,
+    first: Option<u64>,
+    feeder: Option<usize>,
+    highs: HashMap<usize, Vec<u64>>,
 }
 
-struct SwitchArray {
+/// The network, with every module and output numbered in name order.
+pub struct SwitchArray {
     button_presses: u64,
-    rx_low: Option<u64>,
-    switches: HashMap<String, Switch>,
+    names: Vec<String>,
+    modules: Vec<Option<Box<dyn Module>>>,
+    /// Where each module sends its pulses, and which input of the receiver
+    /// they arrive on.
+    outputs: Vec<Vec<(usize, usize)>>,
+    inputs: Vec<Vec<usize>>,
+    broadcaster: usize,
+    /// Modules that have sent a pulse to all their outputs, not yet
+    /// delivered.
+    queue: VecDeque<(usize, Pulse)>,
+    watches: Vec<SinkWatch>,
+    observers: Vec<Box<dyn Observer>>,
     high_pulses: Option<i32>,
     low_pulses: Option<i32>,
 }
 
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    Malformed(usize, String),
+    UnknownKind(usize, String),
+    BadName(usize, String),
+    NoOutputs(String),
+    SelfLoop(String),
+    Duplicate(String),
+    MissingBroadcaster,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Malformed(line, text) => {
+                write!(
+                    f,
+                    "line {}: expected 'module -> outputs', got '{}'",
+                    line, text
+                )
+            }
+            ParseError::UnknownKind(line, token) => {
+                write!(f, "line {}: unknown kind of module '{}'", line, token)
+            }
+            ParseError::BadName(line, name) => {
+                write!(f, "line {}: '{}' is not a valid module name", line, name)
+            }
+            ParseError::NoOutputs(name) => write!(f, "'{}' has no outputs", name),
+            ParseError::SelfLoop(name) => write!(f, "'{}' sends pulses to itself", name),
+            ParseError::Duplicate(name) => write!(f, "'{}' is defined more than once", name),
+            ParseError::MissingBroadcaster => write!(f, "no broadcaster module"),
+        }
+    }
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
 impl Switch {
-    fn from(pattern: (&str, &str)) -> (String, Self) {
-        let switch_type;
-        let id;
-        if pattern.0.len() > 5 {
-            switch_type = SwitchType::Broadcaster;
-            id = pattern.0.to_string();
+    /// Parses one line of the input, like `%a -> b, c`, numbered from 1.
+    fn from(line: usize, text: &str) -> Result<(String, Self), ParseError> {
+        let (token, outputs) = match text.split_once("->") {
+            Some((token, outputs)) => (token.trim(), outputs.trim()),
+            None => return Err(ParseError::Malformed(line, text.to_string())),
+        };
+
+        let (id, module): (String, Box<dyn Module>) = if token == "broadcaster" {
+            (token.to_string(), Box::new(Broadcaster))
         } else {
-            let mut token = pattern.0.chars();
-            if token.nth(0) == Some('%') {
-                switch_type = SwitchType::FlipFlop(FlipFlop::new());
-            } else {
-                switch_type = SwitchType::Conjunction(Conjunction::new());
+            match module::build(token) {
+                Some(built) => built,
+                None => return Err(ParseError::UnknownKind(line, token.to_string())),
             }
-            id = token.collect();
+        };
+        if !valid_name(&id) {
+            return Err(ParseError::BadName(line, id));
         }
 
-        let outputs = pattern.1.split(", ").map(|x| x.to_string()).collect();
+        if outputs.is_empty() {
+            return Err(ParseError::NoOutputs(id));
+        }
+        let outputs: Vec<String> = outputs.split(',').map(|x| x.trim().to_string()).collect();
+        if let Some(bad) = outputs.iter().find(|output| !valid_name(output)) {
+            return Err(ParseError::BadName(line, bad.clone()));
+        }
+        if outputs.contains(&id) {
+            return Err(ParseError::SelfLoop(id));
+        }
 
-        return (
-            id,
-            Self {
-                switch_type,
-                outputs,
-            },
-        );
+        Ok((id, Self { module, outputs }))
     }
 }
 
@@ -116,72 +205,75 @@ impl SwitchArray {
     fn new() -> Self {
         Self {
             button_presses: 0,
-            rx_low: None,
-            switches: HashMap::new(),
+            names: vec![],
+            modules: vec![],
+            outputs: vec![],
+            inputs: vec![],
+            broadcaster: 0,
+            queue: VecDeque::new(),
+            watches: vec![],
+            observers: vec![],
             high_pulses: None,
             low_pulses: None,
         }
     }
 
+    pub fn read(path: &Path) -> Result<Self, ParseError> {
+        let file = File::open(path).expect("Error opening file!");
+        let reader = BufReader::new(file);
+
+        let mut switch_array = SwitchArray::new();
+        switch_array.register_switches(reader.lines().map_while(Result::ok).collect())?;
+        Ok(switch_array)
+    }
+
+    pub fn observe(&mut self, observer: Box<dyn Observer>) {
+        self.observers.push(observer);
+    }
+
     fn pulse(&mut self) {
         self.button_presses += 1;
+        let press = self.button_presses;
+        let Self {
+            names,
+            modules,
+            outputs,
+            broadcaster,
+            queue,
+            watches,
+            observers,
+            ..
+        } = self;
+
+        notify(observers, press, "button", Pulse::Low, &names[*broadcaster]);
         let mut high_pulses = 0;
         let mut low_pulses = 1;
-        let mut queue = VecDeque::new();
-        for out in self.switches.get("broadcaster").unwrap().outputs.clone() {
-            queue.push_back((String::from("broadcaster"), out, Pulse::Low));
-        }
-        while queue.len() > 0 {
-            let (source, receiver, pulse) = queue.pop_front().unwrap();
+        queue.push_back((*broadcaster, Pulse::Low));
+        while let Some((source, pulse)) = queue.pop_front() {
+            let receivers = &outputs[source];
             match pulse {
-                Pulse::High => {
-                    high_pulses += 1;
-                }
-                Pulse::Low => {
-                    low_pulses += 1;
-                }
+                Pulse::High => high_pulses += receivers.len() as i32,
+                Pulse::Low => low_pulses += receivers.len() as i32,
             }
 
-            // hj is the only node sending pulse to rx
-            // when all of hj's inputs are high then rx receives a low signal
-            if receiver == "hj" && pulse == Pulse::High {
-                println!(
-                    "press: {}; {} -{:?}-> {}",
-                    self.button_presses, source, pulse, receiver
-                );
-            }
+            for &(receiver, input) in receivers {
+                notify(observers, press, &names[source], pulse, &names[receiver]);
 
-            let switch_or = self.switches.get_mut(&receiver);
-            if let Some(switch) = switch_or {
-                match switch.switch_type {
-                    SwitchType::FlipFlop(ref mut flop) => {
-                        if let Some(pulse_out) = flop.pulse(pulse) {
-                            for output in switch.outputs.clone() {
-                                queue.push_back((receiver.clone(), output, pulse_out));
-                            }
-                        }
+                for watch in watches.iter_mut() {
+                    if receiver == watch.sink && pulse == watch.level && watch.first.is_none() {
+                        watch.first = Some(press);
                     }
-                    SwitchType::Conjunction(ref mut conj) => {
-                        let pulse_out = conj.pulse(self.button_presses, source, pulse);
-                        for output in switch.outputs.clone() {
-                            queue.push_back((receiver.clone(), output, pulse_out));
-                        }
-                        // hj is the only node sending pulse to rx
-                        // when all of hj's inputs are high then rx receives a low signal
-                        if receiver == "hj" && conj.inputs.len() == conj.first_high.len() {
-                            self.rx_low =
-                                Some(conj.first_high.iter().fold(1, |acc, x| lcm(acc, *x.1)))
+                    if watch.feeder == Some(receiver) && pulse == Pulse::High {
+                        let presses = watch.highs.entry(source).or_default();
+                        if presses.last() != Some(&press) {
+                            presses.push(press);
                         }
                     }
-                    _ => {}
                 }
-            } else {
-                if receiver == String::from("rx") {
-                    match (pulse, self.rx_low) {
-                        (Pulse::Low, None) => {
-                            self.rx_low = Some(self.button_presses);
-                        }
-                        (_, Some(_)) | (Pulse::High, _) => {}
+
+                if let Some(module) = &mut modules[receiver] {
+                    if let Some(pulse_out) = module.receive(input, pulse) {
+                        queue.push_back((receiver, pulse_out));
                     }
                 }
             }
@@ -190,82 +282,329 @@ impl SwitchArray {
         self.low_pulses = Some(low_pulses);
     }
 
-    fn register_switches(&mut self, switches: Vec<String>) {
-        let mut conjunctions = vec![];
-        let mut other_switches = vec![];
-        for l in switches {
-            if l.chars().nth(0) == Some('&') {
-                conjunctions.push(l);
-            } else {
-                other_switches.push(l);
+    fn register_switches(&mut self, switches: Vec<String>) -> Result<(), ParseError> {
+        let mut parsed = HashMap::new();
+        for (i, switch) in switches.iter().enumerate() {
+            if switch.trim().is_empty() {
+                continue;
             }
+            let (name, switch) = Switch::from(i + 1, switch)?;
+            if parsed.contains_key(&name) {
+                return Err(ParseError::Duplicate(name));
+            }
+            parsed.insert(name, switch);
         }
-        for switch in conjunctions.iter().chain(other_switches.iter()) {
-            self.register_switch(switch);
+        match parsed.get("broadcaster") {
+            Some(switch) if switch.module.kind() == Kind::Broadcaster => {}
+            _ => return Err(ParseError::MissingBroadcaster),
         }
-        self.register_inputs();
+        self.compile(parsed);
+        Ok(())
     }
 
-    fn register_switch(&mut self, switch: &String) {
-        let split = switch.split_once(" -> ");
-        if let Some(pattern) = split {
-            let (id, switch) = Switch::from(pattern);
-            self.switches.insert(id, switch);
-        } else {
-            panic!("attempted to register invalid sequence!");
+    /// Numbers the modules and wires them up, telling each its inputs in numbered order.
+    fn compile(&mut self, mut switches: HashMap<String, Switch>) {
+        let mut names: Vec<String> = switches.keys().cloned().collect();
+        for switch in switches.values() {
+            names.extend(switch.outputs.iter().cloned());
         }
+        names.sort();
+        names.dedup();
+
+        let mut targets = vec![vec![]; names.len()];
+        self.modules = names
+            .iter()
+            .enumerate()
+            .map(|(id, name)| {
+                let switch = switches.remove(name)?;
+                targets[id] = switch.outputs;
+                Some(switch.module)
+            })
+            .collect();
+
+        self.outputs = vec![vec![]; names.len()];
+        self.inputs = vec![vec![]; names.len()];
+        for (source, targets) in targets.iter().enumerate() {
+            for target in targets {
+                let target = names.binary_search(target).expect("every output is named");
+                if let Some(module) = &mut self.modules[target] {
+                    module.connect();
+                }
+                self.outputs[source].push((target, self.inputs[target].len()));
+                self.inputs[target].push(source);
+            }
+        }
+        self.broadcaster = names
+            .binary_search_by(|name| name.as_str().cmp("broadcaster"))
+            .expect("broadcaster checked for");
+        self.names = names;
+    }
+
+    fn id(&self, name: &str) -> Option<usize> {
+        self.names.binary_search_by(|n| n.as_str().cmp(name)).ok()
     }
 
-    fn register_inputs(&mut self) {
-        let mut inputs = vec![];
-        for switch in self.switches.clone() {
-            for output in switch.1.outputs {
-                inputs.push((switch.0.clone(), output));
+    /// Every module that can affect those named, themselves included, in a fixed order.
+    pub fn upstream(&self, names: &[String]) -> Vec<String> {
+        let mut seen = vec![false; self.names.len()];
+        let mut found = vec![];
+        for id in names.iter().filter_map(|name| self.id(name)) {
+            if !seen[id] {
+                seen[id] = true;
+                found.push(id);
+            }
+        }
+        let mut next = 0;
+        while next < found.len() {
+            for &input in &self.inputs[found[next]] {
+                if !seen[input] {
+                    seen[input] = true;
+                    found.push(input);
+                }
             }
+            next += 1;
         }
+        // outputs nothing is defined for, like `rx`, have no memory
+        found.retain(|&id| self.modules[id].is_some());
+        found.sort();
+        found.into_iter().map(|id| self.names[id].clone()).collect()
+    }
 
-        for (source, target) in inputs {
-            if let Some(switch) = self.switches.get_mut(&target) {
-                match switch.switch_type {
-                    SwitchType::Conjunction(ref mut conj) => {
-                        conj.inputs.insert(source, Pulse::Low);
-                    }
-                    _ => {}
-                };
+    /// The memory of every module in `modules`.
+    pub fn snapshot(&self, modules: &[String]) -> Snapshot {
+        self.snapshot_ids(&self.module_ids(modules))
+    }
+
+    fn module_ids(&self, modules: &[String]) -> Vec<usize> {
+        modules
+            .iter()
+            .map(|name| match self.id(name) {
+                Some(id) if self.modules[id].is_some() => id,
+                _ => panic!("no module named '{}'", name),
+            })
+            .collect()
+    }
+
+    fn snapshot_ids(&self, ids: &[usize]) -> Snapshot {
+        let mut snapshot = Snapshot::default();
+        for module in ids.iter().filter_map(|&id| self.modules[id].as_ref()) {
+            module.save(&mut snapshot);
+        }
+        snapshot
+    }
+
+    /// Presses the button until the state of `modules` repeats, or `budget`
+    /// presses have gone by.
+    pub fn find_cycle(&mut self, modules: &[String], budget: u64) -> Option<NetworkCycle> {
+        let ids = self.module_ids(modules);
+        let mut seen = HashMap::new();
+        let mut pulses = vec![];
+        for press in 0..=budget {
+            let snapshot = self.snapshot_ids(&ids);
+            if let Some(&prefix) = seen.get(&snapshot) {
+                return Some(NetworkCycle {
+                    prefix,
+                    period: press - prefix,
+                    pulses,
+                });
             }
+            seen.insert(snapshot, press);
+            self.pulse();
+            pulses.push((
+                self.low_pulses.unwrap() as u64,
+                self.high_pulses.unwrap() as u64,
+            ));
         }
+        None
+    }
+
+    pub fn graph(&self) -> Graph {
+        Graph::new(
+            self.names
+                .iter()
+                .enumerate()
+                .filter_map(|(id, name)| {
+                    let module = self.modules[id].as_ref()?;
+                    let outputs = self.outputs[id]
+                        .iter()
+                        .map(|&(target, _)| self.names[target].clone())
+                        .collect();
+                    Some((name.clone(), module.kind(), outputs))
+                })
+                .collect(),
+        )
+    }
+
+    /// Every module in the network.
+    pub fn modules(&self) -> Vec<String> {
+        self.names
+            .iter()
+            .enumerate()
+            .filter(|&(id, _)| self.modules[id].is_some())
+            .map(|(_, name)| name.clone())
+            .collect()
+    }
+
+    /// Starts watching `sink` for a pulse at `level`; call before pressing the button.
+    fn watch_sink(&mut self, sink: &str, level: Pulse) -> Result<usize, SinkError> {
+        let id = match self.id(sink) {
+            Some(id) if !self.inputs[id].is_empty() => id,
+            _ => return Err(SinkError::NoInputs(sink.to_string())),
+        };
+        let feeder = match self.inputs[id].as_slice() {
+            [feeder]
+                if level == Pulse::Low
+                    && self.modules[*feeder]
+                        .as_ref()
+                        .is_some_and(|module| module.kind() == Kind::Conjunction) =>
+            {
+                Some(*feeder)
+            }
+            _ => None,
+        };
+        self.watches.push(SinkWatch {
+            sink: id,
+            level,
+            first: None,
+            feeder,
+            highs: HashMap::new(),
+        });
+        Ok(self.watches.len() - 1)
+    }
+
+    /// The first press a watched sink gets its pulse on, within `budget` presses.
+    fn first_press(&mut self, watch: usize, budget: u64) -> Result<Option<FirstPress>, SinkError> {
+        let mut predicted = None;
+        let mut failed = None;
+        loop {
+            if let Some(press) = self.watches[watch].first {
+                return Ok(Some(FirstPress::Seen(press)));
+            }
+            if predicted.is_none() {
+                // the inputs don't fit the model, but pressing may still find it
+                predicted = self.predict(watch).unwrap_or_else(|why| {
+                    self.watches[watch].feeder = None;
+                    failed = Some(why);
+                    None
+                });
+            }
+            match predicted {
+                Some(press) if press > budget => return Ok(Some(FirstPress::Predicted(press))),
+                // pressed as far as the prediction without the pulse turning
+                // up, so it was wrong: keep pressing without predicting
+                Some(press) if self.button_presses >= press => {
+                    self.watches[watch].feeder = None;
+                    predicted = None;
+                }
+                _ => {}
+            }
+            if self.button_presses >= budget {
+                return failed.map_or(Ok(None), Err);
+            }
+            self.pulse();
+        }
+    }
+
+    /// When a watched sink should first get a low pulse, once its feeder's inputs keep time.
+    fn predict(&self, watch: usize) -> Result<Option<u64>, SinkError> {
+        let watch = &self.watches[watch];
+        let inputs = match watch.feeder {
+            Some(feeder) => &self.inputs[feeder],
+            None => return Ok(None),
+        };
+        let settled = inputs
+            .iter()
+            .all(|input| watch.highs.get(input).is_some_and(|p| p.len() >= CYCLES));
+        if !settled {
+            return Ok(None);
+        }
+        let mut schedules = vec![];
+        for input in inputs {
+            schedules.push(schedule(&self.names[*input], &watch.highs[input])?);
+        }
+        first_common_press(&schedules).map(Some)
+    }
+}
+
+/// Tells every observer about a pulse being delivered.
+fn notify(
+    observers: &mut [Box<dyn Observer>],
+    press: u64,
+    source: &str,
+    pulse: Pulse,
+    receiver: &str,
+) {
+    let event = Event {
+        press,
+        source,
+        pulse,
+        receiver,
+    };
+    for observer in observers {
+        observer.observe(&event);
     }
 }
 
-pub fn pulse(path: &Path) -> (Option<i32>, Option<u64>) {
-    let file = File::open(path).expect("Error opening file!");
-    let reader = BufReader::new(file);
+/// The `(start, period)` of an input's high pulses, if they are evenly
+/// spaced.
+fn schedule(input: &str, presses: &[u64]) -> Result<(u64, u64), SinkError> {
+    let period = presses[1] - presses[0];
+    if presses.windows(2).any(|w| w[1] - w[0] != period) {
+        return Err(SinkError::Irregular(input.to_string(), presses.to_vec()));
+    }
+    Ok((presses[0], period))
+}
 
-    let mut switch_array = SwitchArray::new();
-    switch_array.register_switches(reader.lines().flatten().collect());
+/// The first press that every `(start, period)` schedule lands on, merging
+/// them one at a time as in the Chinese remainder theorem.
+fn first_common_press(schedules: &[(u64, u64)]) -> Result<u64, SinkError> {
+    let overflow = || SinkError::Overflow(schedules.to_vec());
+    let (mut start, mut period) = (0u64, 1);
+    for &(s, p) in schedules {
+        // the combined schedule only moves by multiples of the gcd of the two
+        // periods, so unless it is already in step with this one it never is
+        if start.abs_diff(s) % gcd(period, p) != 0 {
+            return Err(SinkError::NeverAligned(schedules.to_vec()));
+        }
+        // step along the combined schedule until it lines up with this one
+        while start < s || (start - s) % p != 0 {
+            start = start.checked_add(period).ok_or_else(overflow)?;
+        }
+        period = lcm(period, p).ok_or_else(overflow)?;
+    }
+    Ok(start)
+}
+
+/// The pulse product over `presses` presses and the first press each query is met, if known.
+pub fn pulse(
+    switch_array: &mut SwitchArray,
+    presses: u64,
+    queries: &[(String, Pulse)],
+    budget: u64,
+) -> (u64, Vec<Result<Option<FirstPress>, SinkError>>) {
+    let watches: Vec<_> = queries
+        .iter()
+        .map(|(sink, level)| switch_array.watch_sink(sink, *level))
+        .collect();
 
     let mut total_low = 0;
     let mut total_high = 0;
-    let mut pt2 = None;
-    for _ in 0..1000 {
+    for _ in 0..presses {
         switch_array.pulse();
-        total_low += switch_array.low_pulses.unwrap();
-        total_high += switch_array.high_pulses.unwrap();
+        total_low += switch_array.low_pulses.unwrap() as u64;
+        total_high += switch_array.high_pulses.unwrap() as u64;
     }
 
-    for _ in 0..9000 {
-        switch_array.pulse();
-        if let Some(rx_low) = switch_array.rx_low {
-            pt2 = Some(rx_low);
-            break;
-        }
-    }
+    let firsts = watches
+        .into_iter()
+        .map(|watch| watch.and_then(|watch| switch_array.first_press(watch, budget)))
+        .collect();
 
-    return (Some(total_high * total_low), pt2);
+    (total_high * total_low, firsts)
 }
 
-fn lcm(a: u64, b: u64) -> u64 {
-    (a * b) / gcd(a, b)
+fn lcm(a: u64, b: u64) -> Option<u64> {
+    (a / gcd(a, b)).checked_mul(b)
 }
 
 use std::cmp::min;
@@ -312,3 +651,284 @@ pub fn gcd(mut u: u64, mut v: u64) -> u64 {
         v >>= v.trailing_zeros();
     }
 }
+
+#[test]
+fn example_pulse_counts() {
+    let mut switch_array = SwitchArray::read(Path::new("test-1.txt")).unwrap();
+    assert_eq!(pulse(&mut switch_array, 1000, &[], 0).0, 32_000_000);
+    let mut switch_array = SwitchArray::read(Path::new("test-2.txt")).unwrap();
+    let queries = [
+        ("output".to_string(), Pulse::Low),
+        ("inv".to_string(), Pulse::Low),
+        ("a".to_string(), Pulse::High),
+        ("rx".to_string(), Pulse::Low),
+    ];
+    let (product, firsts) = pulse(&mut switch_array, 1000, &queries, 2000);
+    assert_eq!(product, 11_687_500);
+    // `a` only ever hears the broadcaster's low pulses
+    assert_eq!(
+        firsts,
+        [
+            Ok(Some(FirstPress::Seen(1))),
+            Ok(Some(FirstPress::Seen(2))),
+            Ok(None),
+            Err(SinkError::NoInputs("rx".into()))
+        ]
+    );
+    assert_eq!(switch_array.button_presses, 2000);
+}
+
+#[cfg(test)]
+fn test_array(lines: &[String]) -> SwitchArray {
+    let mut switch_array = SwitchArray::new();
+    switch_array.register_switches(lines.to_vec()).unwrap();
+    switch_array
+}
+
+/// A puzzle-like network: a flip-flop counter per period, all feeding `con` then `sink`.
+#[cfg(test)]
+fn counter_network(periods: &[u32]) -> Vec<String> {
+    let mut starts = vec![];
+    let mut lines = vec![];
+    for (n, &period) in periods.iter().enumerate() {
+        let bits = 32 - period.leading_zeros();
+        let name = |i: u32| format!("{}{}", (b'a' + n as u8) as char, i);
+        let (conj, inverter) = (name(90), name(91));
+        let mut resets = vec![name(0)];
+        starts.push(name(0));
+        for i in 0..bits {
+            let mut outputs = vec![];
+            if i + 1 < bits {
+                outputs.push(name(i + 1));
+            }
+            if period & (1 << i) != 0 {
+                outputs.push(conj.clone());
+            } else {
+                resets.push(name(i));
+            }
+            lines.push(format!("%{} -> {}", name(i), outputs.join(", ")));
+        }
+        resets.push(inverter.clone());
+        lines.push(format!("&{} -> {}", conj, resets.join(", ")));
+        lines.push(format!("&{} -> con", inverter));
+    }
+    lines.push(format!("broadcaster -> {}", starts.join(", ")));
+    lines.push("&con -> sink".to_string());
+    lines
+}
+
+#[test]
+fn predicted_first_low() {
+    let lines = counter_network(&[5, 7, 11]);
+    let mut switch_array = test_array(&lines);
+    let watch = switch_array.watch_sink("sink", Pulse::Low).unwrap();
+    // after pressing only enough to see each counter cycle a few times
+    assert_eq!(
+        switch_array.first_press(watch, 100),
+        Ok(Some(FirstPress::Predicted(385)))
+    );
+    assert_eq!(switch_array.button_presses, 33);
+    // and checked when the budget reaches it
+    assert_eq!(
+        switch_array.first_press(watch, 1000),
+        Ok(Some(FirstPress::Seen(385)))
+    );
+    assert_eq!(switch_array.button_presses, 385);
+
+    // pressing until it happens agrees
+    let mut switch_array = test_array(&lines);
+    let watch = switch_array.watch_sink("sink", Pulse::Low).unwrap();
+    switch_array.watches[watch].feeder = None;
+    assert_eq!(switch_array.first_press(watch, 100), Ok(None));
+    assert_eq!(
+        switch_array.first_press(watch, 1000),
+        Ok(Some(FirstPress::Seen(385)))
+    );
+
+    // an input that doesn't keep time gives up predicting, not searching
+    let mut switch_array = test_array(&lines);
+    let watch = switch_array.watch_sink("sink", Pulse::Low).unwrap();
+    let input = switch_array.id("a91").unwrap();
+    switch_array.watches[watch]
+        .highs
+        .insert(input, vec![1, 2, 4]);
+    assert_eq!(
+        switch_array.first_press(watch, 1000),
+        Ok(Some(FirstPress::Seen(385)))
+    );
+
+    // two inverters hold back the second counter's pulses, so its high
+    // reaches `con` after the first counter has gone low again
+    let mut delayed = counter_network(&[5, 7]);
+    let inverter = delayed.iter().position(|l| l == "&b91 -> con").unwrap();
+    delayed.splice(
+        inverter..=inverter,
+        ["&b91 -> d1", "!d1 -> d2", "!d2 -> con"].map(String::from),
+    );
+    let mut switch_array = test_array(&delayed);
+    let watch = switch_array.watch_sink("sink", Pulse::Low).unwrap();
+    assert_eq!(
+        switch_array.first_press(watch, 30),
+        Ok(Some(FirstPress::Predicted(35)))
+    );
+    assert_eq!(switch_array.first_press(watch, 1000), Ok(None));
+    assert_eq!(switch_array.button_presses, 1000);
+
+    assert_eq!(first_common_press(&[(2, 4), (3, 9)]), Ok(30));
+    // odd and even presses never meet
+    assert_eq!(
+        first_common_press(&[(2, 2), (1, 2)]),
+        Err(SinkError::NeverAligned(vec![(2, 2), (1, 2)]))
+    );
+    // only once the budget had run out
+    assert_eq!(switch_array.button_presses, 1000);
+    // the same, from a network whose inputs go high on alternate presses
+    let misaligned = [
+        "broadcaster -> c, f, n1",
+        "#2c -> x",
+        "%f -> y",
+        "&n1 -> n2",
+        "&n2 -> x, y",
+        "@x -> con",
+        "@y -> con",
+        "&con -> sink",
+    ];
+    let mut switch_array = test_array(&misaligned.map(String::from));
+    let watch = switch_array.watch_sink("sink", Pulse::Low).unwrap();
+    assert_eq!(
+        switch_array.first_press(watch, 1000),
+        Err(SinkError::NeverAligned(vec![(2, 2), (1, 2)]))
+    );
+    let huge = [(7, 1 << 40), (7, (1 << 40) - 1)];
+    assert_eq!(
+        first_common_press(&huge),
+        Err(SinkError::Overflow(huge.to_vec()))
+    );
+    assert_eq!(
+        test_array(&lines).watch_sink("nowhere", Pulse::Low),
+        Err(SinkError::NoInputs("nowhere".into()))
+    );
+}
+
+#[cfg(test)]
+struct Record(std::rc::Rc<std::cell::RefCell<Vec<String>>>);
+
+#[cfg(test)]
+impl Observer for Record {
+    fn observe(&mut self, event: &Event) {
+        self.0
+            .borrow_mut()
+            .push(format!("{}: {}", event.press, event));
+    }
+}
+
+#[test]
+fn observed_pulses() {
+    let events = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+    let mut switch_array = SwitchArray::read(Path::new("test-2.txt")).unwrap();
+    switch_array.observe(Box::new(Record(events.clone())));
+    switch_array.pulse();
+    switch_array.pulse();
+    let events = events.borrow();
+    assert_eq!(
+        events[..8],
+        [
+            "1: button -low-> broadcaster",
+            "1: broadcaster -low-> a",
+            "1: a -high-> inv",
+            "1: a -high-> con",
+            "1: inv -low-> b",
+            "1: con -high-> output",
+            "1: b -high-> con",
+            "1: con -low-> output",
+        ]
+    );
+    assert_eq!(events[8], "2: button -low-> broadcaster");
+}
+
+#[test]
+fn whole_network_cycles() {
+    let mut switch_array = SwitchArray::read(Path::new("test-2.txt")).unwrap();
+    let modules = switch_array.modules();
+    let cycle = switch_array.find_cycle(&modules, 100).unwrap();
+    assert_eq!((cycle.prefix, cycle.period), (0, 4));
+    assert_eq!(cycle.pulses(1000), (4250, 2750));
+
+    let mut switch_array = SwitchArray::read(Path::new("test-1.txt")).unwrap();
+    let modules = switch_array.modules();
+    let cycle = switch_array.find_cycle(&modules, 100).unwrap();
+    assert_eq!((cycle.prefix, cycle.period), (0, 1));
+    assert_eq!(cycle.pulses(1000), (8000, 4000));
+}
+
+#[test]
+fn sub_network_cycles() {
+    let mut switch_array = test_array(&counter_network(&[5, 7, 11]));
+    let counter = switch_array.upstream(&["b91".to_string()]);
+    // the broadcaster, three flip-flops and two conjunctions
+    assert_eq!(counter.len(), 6);
+    let cycle = switch_array.find_cycle(&counter, 100).unwrap();
+    // the inverter only remembers a high pulse once the counter has sent one
+    assert_eq!((cycle.prefix, cycle.period), (1, 7));
+
+    let everything = switch_array.modules();
+    assert_eq!(switch_array.upstream(&["sink".to_string()]), everything);
+    assert_eq!(switch_array.find_cycle(&everything, 100), None);
+}
+
+#[test]
+fn counters_found() {
+    let graph = test_array(&counter_network(&[5, 7, 11])).graph();
+    let counters = graph.counters();
+    let periods: Vec<u64> = counters.iter().map(|c| c.period).collect();
+    assert_eq!(periods, [5, 7, 11]);
+    assert_eq!(counters[2].conjunction, "c90");
+    assert_eq!(counters[2].bits, ["c0", "c1", "c2", "c3"]);
+    // each counter loops through its conjunction
+    assert_eq!(graph.strongly_connected().len(), 3);
+    assert_eq!(graph.undefined(), ["sink"]);
+    assert!(graph.unreachable().is_empty());
+}
+
+#[test]
+fn parse_errors() {
+    let parse = |lines: &[&str]| {
+        let lines = lines.iter().map(|l| l.to_string()).collect();
+        SwitchArray::new().register_switches(lines).err()
+    };
+    // long names are not mistaken for the broadcaster
+    assert_eq!(parse(&["broadcaster -> abcdef", "%abcdef -> output"]), None);
+    assert_eq!(
+        parse(&["broadcaster -> a", "%a b"]),
+        Some(ParseError::Malformed(2, "%a b".into()))
+    );
+    assert_eq!(
+        parse(&["broadcaster -> a", "a -> b"]),
+        Some(ParseError::UnknownKind(2, "a".into()))
+    );
+    assert_eq!(
+        parse(&["broadcaster -> a", "% -> b"]),
+        Some(ParseError::BadName(2, "".into()))
+    );
+    assert_eq!(
+        parse(&["broadcaster -> a, , b"]),
+        Some(ParseError::BadName(1, "".into()))
+    );
+    assert_eq!(
+        parse(&["broadcaster -> a", "&a ->"]),
+        Some(ParseError::NoOutputs("a".into()))
+    );
+    assert_eq!(
+        parse(&["broadcaster -> a", "%a -> b, a"]),
+        Some(ParseError::SelfLoop("a".into()))
+    );
+    assert_eq!(
+        parse(&["broadcaster -> a", "%a -> b", "&a -> b"]),
+        Some(ParseError::Duplicate("a".into()))
+    );
+    assert_eq!(parse(&["%a -> b"]), Some(ParseError::MissingBroadcaster));
+    assert_eq!(
+        parse(&["%broadcaster -> a"]),
+        Some(ParseError::MissingBroadcaster)
+    );
+}
@@ -0,0 +1,109 @@
+use std::{fmt, io::Write};
+
+use crate::module::Pulse;
+
+/// A single pulse being delivered, during the given button press.
+#[derive(Debug, PartialEq)]
+pub struct Event<'a> {
+    pub press: u64,
+    pub source: &'a str,
+    pub pulse: Pulse,
+    pub receiver: &'a str,
+}
+
+impl fmt::Display for Event<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} -{}-> {}", self.source, self.pulse, self.receiver)
+    }
+}
+
+/// Told about every pulse the network delivers, in order, including the
+/// button's pulse to the broadcaster.
+pub trait Observer {
+    fn observe(&mut self, event: &Event);
+}
+
+/// Which events a trace keeps. Each part left as `None` keeps everything.
+#[derive(Debug, Default)]
+pub struct TraceFilter {
+    /// Modules that must send or receive the pulse.
+    pub nodes: Option<Vec<String>>,
+    pub level: Option<Pulse>,
+    /// First and last press, inclusive.
+    pub presses: Option<(u64, u64)>,
+}
+
+impl TraceFilter {
+    fn matches(&self, event: &Event) -> bool {
+        let node = self.nodes.as_ref().is_none_or(|nodes| {
+            nodes
+                .iter()
+                .any(|node| node == event.source || node == event.receiver)
+        });
+        let level = self.level.is_none_or(|level| level == event.pulse);
+        let press = self
+            .presses
+            .is_none_or(|(first, last)| (first..=last).contains(&event.press));
+        node && level && press
+    }
+}
+
+/// Writes the events passing `filter` as in the puzzle text, one per line,
+/// with a heading for each press.
+pub struct Trace<W: Write> {
+    filter: TraceFilter,
+    out: W,
+    press: u64,
+}
+
+impl<W: Write> Trace<W> {
+    pub fn new(filter: TraceFilter, out: W) -> Self {
+        Self {
+            filter,
+            out,
+            press: 0,
+        }
+    }
+}
+
+impl<W: Write> Observer for Trace<W> {
+    fn observe(&mut self, event: &Event) {
+        if !self.filter.matches(event) {
+            return;
+        }
+        if event.press != self.press {
+            self.press = event.press;
+            writeln!(self.out, "press {}:", event.press).expect("error writing trace!");
+        }
+        writeln!(self.out, "{}", event).expect("error writing trace!");
+    }
+}
+
+#[test]
+fn filtered_trace() {
+    let events = [
+        (1, "button", Pulse::Low, "broadcaster"),
+        (1, "broadcaster", Pulse::Low, "a"),
+        (1, "a", Pulse::High, "b"),
+        (2, "a", Pulse::Low, "b"),
+        (3, "a", Pulse::High, "c"),
+    ];
+    let filter = TraceFilter {
+        nodes: Some(vec!["a".to_string()]),
+        level: None,
+        presses: Some((1, 2)),
+    };
+    let mut trace = Trace::new(filter, vec![]);
+    for (press, source, pulse, receiver) in events {
+        trace.observe(&Event {
+            press,
+            source,
+            pulse,
+            receiver,
+        });
+    }
+    assert_eq!(
+        String::from_utf8(trace.out).unwrap(),
+        "press 1:\nbroadcaster -low-> a\na -high-> b\npress 2:\na -low-> b\n"
+    );
+}
@@ -0,0 +1,194 @@
+//! Puzzle results printed as plain text at a chosen verbosity, or gathered
+//! into JSON.
+
+use std::fmt;
+
+/// How much plain-text output to print. Each level includes the ones below.
+#[derive(Debug, PartialEq, PartialOrd, Copy, Clone)]
+pub enum Verbosity {
+    /// Just the answers.
+    Quiet,
+    /// Answers plus a summary of how they were reached.
+    Normal,
+    /// Everything, including the parsed input.
+    Verbose,
+}
+
+/// A JSON value, enough to describe puzzle inputs and answers without
+/// pulling in a serialisation crate.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Int(i128),
+    Float(f64),
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn object(fields: Vec<(&str, Json)>) -> Self {
+        Json::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+}
+
+impl From<i64> for Json {
+    fn from(n: i64) -> Self {
+        Json::Int(n as i128)
+    }
+}
+
+impl From<i128> for Json {
+    fn from(n: i128) -> Self {
+        Json::Int(n)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(n: usize) -> Self {
+        Json::Int(n as i128)
+    }
+}
+
+impl From<f64> for Json {
+    fn from(n: f64) -> Self {
+        Json::Float(n)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Self {
+        Json::Str(s.to_string())
+    }
+}
+
+impl<T: Into<Json>> From<Vec<T>> for Json {
+    fn from(items: Vec<T>) -> Self {
+        Json::Array(items.into_iter().map(|item| item.into()).collect())
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(item: Option<T>) -> Self {
+        item.map_or(Json::Null, |item| item.into())
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Int(n) => write!(f, "{}", n),
+            // JSON has no representation for NaN or infinity
+            Json::Float(n) if !n.is_finite() => write!(f, "null"),
+            Json::Float(n) => write!(f, "{}", n),
+            Json::Str(s) => write_string(f, s),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (k, item) in items.iter().enumerate() {
+                    if k > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (k, (key, value)) in fields.iter().enumerate() {
+                    if k > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// Collects a program's results. In text mode each result is printed as it
+/// arrives, if the verbosity allows; in JSON mode everything is gathered
+/// into a single object and printed by `finish`.
+pub struct Output {
+    verbosity: Verbosity,
+    json: bool,
+    fields: Vec<(String, Json)>,
+}
+
+impl Output {
+    pub fn new(verbosity: Verbosity, json: bool) -> Self {
+        Self {
+            verbosity,
+            json,
+            fields: vec![],
+        }
+    }
+
+    /// Records `value` under `key`, printing `text` at `level` or above.
+    /// The text is only built if it will be printed.
+    pub fn field(
+        &mut self,
+        level: Verbosity,
+        key: &str,
+        value: Json,
+        text: impl FnOnce() -> String,
+    ) {
+        if self.json {
+            self.fields.push((key.to_string(), value));
+        } else if level <= self.verbosity {
+            println!("{}", text());
+        }
+    }
+
+    /// An answer to the puzzle, printed as `key: value` at every verbosity.
+    pub fn answer(&mut self, key: &str, value: impl Into<Json>) {
+        let value = value.into();
+        let text = format!("{}: {}", key, value);
+        self.field(Verbosity::Quiet, key, value, || text);
+    }
+
+    pub fn finish(self) {
+        if self.json {
+            println!("{}", Json::Object(self.fields));
+        }
+    }
+}
+
+#[test]
+fn json_encoding() {
+    let value = Json::object(vec![
+        ("name", "a \"quoted\"\nline".into()),
+        ("counts", vec![1usize, 2, 3].into()),
+        ("missing", Json::from(None::<i64>)),
+        ("ratio", 0.5.into()),
+        ("nested", Json::object(vec![("ok", Json::Bool(true))])),
+    ]);
+    assert_eq!(
+        value.to_string(),
+        r#"{"name":"a \"quoted\"\nline","counts":[1,2,3],"missing":null,"ratio":0.5,"nested":{"ok":true}}"#
+    );
+}